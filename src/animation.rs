@@ -0,0 +1,80 @@
+// An animation of the moves along a line of the game, written as a GIF.
+
+use diagram::{Diagram, Mark, Style};
+use gif::{Encoder, Palette};
+use raster;
+use {collect_moves, Instruction, Model, Position};
+
+// a diagram of every node from the root to the end of the path, with a
+// circle on the last move, the position is replayed once along the way
+pub fn frames(game: &Model, path: &[usize]) -> Result<Vec<Diagram>, String> {
+    let (width, height) = (game.width, game.height);
+    let mut position = Position::new(width, height);
+    let mut frames = vec![];
+    let mut node = &game.node;
+    for depth in 0..path.len() + 1 {
+        if depth > 0 {
+            node = &node.children[path[depth - 1]];
+        }
+        let mut last = None;
+        for instruction in collect_moves(node, &[]) {
+            position.apply(&instruction);
+            if let Instruction::Move((x, y), _) = instruction {
+                if x < width && y < height {
+                    last = Some(y * width + x);
+                }
+            }
+        }
+        let mut diagram = Diagram::at(
+            game,
+            &path[..depth],
+            position.board.clone(),
+            vec![None; width * height],
+            vec![],
+        )?;
+        if let Some(pos) = last {
+            if diagram.marks[pos].is_none() && position.board[pos] != 0 {
+                diagram.marks[pos] = Some(Mark::Circle);
+            }
+        }
+        frames.push(diagram);
+    }
+    Ok(frames)
+}
+
+pub fn to_gif(frames: &[Diagram], style: &Style) -> Result<Vec<u8>, String> {
+    let theme = raster::theme(&style.theme).ok_or(format!(
+        "unknown theme {}, use {}",
+        style.theme,
+        raster::THEMES.join(", ")
+    ))?;
+    let render = |diagram: &Diagram| raster::render(diagram, style.size, &theme);
+    let (first, last) = match (frames.first(), frames.last()) {
+        (Some(first), Some(last)) => (render(first), render(last)),
+        _ => return Err("there are no moves".to_string()),
+    };
+
+    // every frame has room for the longest comment
+    let captions: Vec<Vec<String>> = frames
+        .iter()
+        .map(|diagram| {
+            if style.captions {
+                raster::caption(diagram, first.width)
+            } else {
+                vec![]
+            }
+        })
+        .collect();
+    let rows = captions.iter().map(|lines| lines.len()).max().unwrap_or(0);
+    let captioned =
+        |canvas: &raster::Canvas, i: usize| raster::add_caption(canvas, &captions[i], rows, &theme);
+
+    // the first and the last frame have most of the colors
+    let first = captioned(&first, 0);
+    let palette = Palette::new(&[&first, &captioned(&last, frames.len() - 1)]);
+    let mut encoder = Encoder::new(first.width, first.height, palette);
+    for (i, diagram) in frames.iter().enumerate() {
+        encoder.add_frame(&captioned(&render(diagram), i), style.delay / 10);
+    }
+    Ok(encoder.finish())
+}
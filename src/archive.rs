@@ -0,0 +1,154 @@
+// Reading zip archives of game records, the files are listed from the
+// central directory at the end of the archive and are stored or deflated.
+
+use deflate::{crc32, inflate, MAX_SIZE};
+
+pub struct Entry {
+    pub name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    offset: usize,
+}
+
+fn u16_at(data: &[u8], i: usize) -> usize {
+    data[i] as usize | (data[i + 1] as usize) << 8
+}
+
+fn u32_at(data: &[u8], i: usize) -> usize {
+    u16_at(data, i) | u16_at(data, i + 2) << 16
+}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06")
+}
+
+// the archive and the file in it of a path like games.zip/2001/game.sgf
+pub fn split(path: &str) -> Option<(&str, &str)> {
+    let lower = path.to_lowercase();
+    lower
+        .find(".zip/")
+        .filter(|_| lower.len() == path.len())
+        .map(|i| (&path[..i + 4], &path[i + 5..]))
+}
+
+// the files of the archive without the directories
+pub fn entries(data: &[u8]) -> Result<Vec<Entry>, String> {
+    // the end of central directory record, followed by a comment of up to
+    // 64k bytes
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(65536 + 22)
+        .find(|&i| data[i..].starts_with(b"PK\x05\x06"))
+        .ok_or("not a zip archive")?;
+    let count = u16_at(data, end + 10);
+    let mut i = u32_at(data, end + 16);
+    let mut entries = vec![];
+    for _ in 0..count {
+        if i + 46 > data.len() || !data[i..].starts_with(b"PK\x01\x02") {
+            return Err("corrupt zip archive".to_string());
+        }
+        let name_length = u16_at(data, i + 28);
+        let skip = u16_at(data, i + 30) + u16_at(data, i + 32);
+        let name = data
+            .get(i + 46..i + 46 + name_length)
+            .ok_or("corrupt zip archive")?;
+        let entry = Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(data, i + 10) as u16,
+            crc: u32_at(data, i + 16) as u32,
+            compressed_size: u32_at(data, i + 20),
+            size: u32_at(data, i + 24),
+            offset: u32_at(data, i + 42),
+        };
+        if !entry.name.ends_with('/') {
+            entries.push(entry);
+        }
+        i += 46 + name_length + skip;
+    }
+    Ok(entries)
+}
+
+// the contents of a file of the archive
+pub fn extract(data: &[u8], entry: &Entry) -> Result<Vec<u8>, String> {
+    if entry.size > MAX_SIZE {
+        return Err(format!("{} is too large", entry.name));
+    }
+    let i = entry.offset;
+    if i + 30 > data.len() || !data[i..].starts_with(b"PK\x03\x04") {
+        return Err(format!("corrupt zip archive at {}", entry.name));
+    }
+    let start = i + 30 + u16_at(data, i + 26) + u16_at(data, i + 28);
+    let compressed = data
+        .get(start..start + entry.compressed_size)
+        .ok_or(format!("corrupt zip archive at {}", entry.name))?;
+    let contents = match entry.method {
+        0 => compressed.to_vec(),
+        // the size from the directory is the limit, a larger file is corrupt
+        8 => {
+            inflate(compressed, entry.size)
+                .map_err(|_| format!("{} is corrupt", entry.name))?
+                .0
+        }
+        method => {
+            return Err(format!(
+                "{} is compressed with the unsupported method {}",
+                entry.name, method
+            ))
+        }
+    };
+    if contents.len() != entry.size || crc32(&contents) != entry.crc {
+        return Err(format!("{} is corrupt", entry.name));
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip() {
+        // a directory, a stored file and a deflated one, written by Python
+        let data = [
+            0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00,
+            0x00, 0x00, 0x32, 0x30, 0x30, 0x31, 0x2f, 0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0xe4, 0xaa, 0x52, 0x5d, 0x1a, 0x26, 0x93, 0x00, 0x0e, 0x00, 0x00,
+            0x00, 0x0e, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x32, 0x30, 0x30, 0x31, 0x2f,
+            0x61, 0x2e, 0x73, 0x67, 0x66, 0x28, 0x3b, 0x47, 0x4d, 0x5b, 0x31, 0x5d, 0x3b, 0x42,
+            0x5b, 0x61, 0x61, 0x5d, 0x29, 0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08,
+            0x00, 0xe4, 0xaa, 0x52, 0x5d, 0x97, 0xfa, 0xd3, 0x57, 0x16, 0x00, 0x00, 0x00, 0x1a,
+            0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x62, 0x2e, 0x73, 0x67, 0x66, 0xd3, 0xb0,
+            0x76, 0xf7, 0x8d, 0x36, 0x8c, 0xb5, 0x76, 0x8a, 0x4e, 0x4c, 0x8c, 0xb5, 0x0e, 0x8f,
+            0x4e, 0x4a, 0x82, 0xb2, 0x35, 0x01, 0x50, 0x4b, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x32, 0x30, 0x30, 0x31,
+            0x2f, 0x50, 0x4b, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe4,
+            0xaa, 0x52, 0x5d, 0x1a, 0x26, 0x93, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x00,
+            0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0x01, 0x23, 0x00, 0x00, 0x00, 0x32, 0x30, 0x30, 0x31, 0x2f, 0x61, 0x2e, 0x73, 0x67,
+            0x66, 0x50, 0x4b, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0xe4,
+            0xaa, 0x52, 0x5d, 0x97, 0xfa, 0xd3, 0x57, 0x16, 0x00, 0x00, 0x00, 0x1a, 0x00, 0x00,
+            0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0x01, 0x59, 0x00, 0x00, 0x00, 0x62, 0x2e, 0x73, 0x67, 0x66, 0x50, 0x4b, 0x05, 0x06,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x03, 0x00, 0x9e, 0x00, 0x00, 0x00, 0x92, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        assert!(is_zip(&data));
+        assert!(entries(&data[..300]).is_err());
+        let entries = entries(&data).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["2001/a.sgf", "b.sgf"]);
+        assert_eq!(extract(&data, &entries[0]).unwrap(), b"(;GM[1];B[aa])");
+        assert_eq!(
+            extract(&data, &entries[1]).unwrap(),
+            b"(;GM[1];B[aa];W[bb];B[aa])"
+        );
+
+        assert_eq!(split("games/Go.ZIP/b.sgf"), Some(("games/Go.ZIP", "b.sgf")));
+        assert_eq!(split("games/b.sgf"), None);
+    }
+}
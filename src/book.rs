@@ -0,0 +1,295 @@
+// A printable game record: the main line split into numbered diagrams with
+// the comments after each, and the variations as diagrams of their own,
+// written as LaTeX for the igo package.
+
+use game_tree::GameNode;
+use Instruction::*;
+use {collect_moves, format_point, get_board, load_collection, GoColor, Model, Position};
+
+const USAGE: &str = "Usage: sgf-navigator book file [--moves 50]";
+
+// the book subcommand, the LaTeX document is written to stdout
+pub fn book_file(args: &[String]) -> Result<(), String> {
+    let (file_name, moves) = match args {
+        [file_name] => (file_name, 50),
+        [file_name, option, n] | [option, n, file_name] if option == "--moves" => (
+            file_name,
+            n.parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or(format!("invalid number of moves {}", n))?,
+        ),
+        _ => return Err(USAGE.to_string()),
+    };
+    let mut collection = load_collection(file_name)?;
+    if collection.games.is_empty() {
+        return Err(format!("{} contains no game", file_name));
+    }
+    let game =
+        get_board(collection.games.remove(0)).map_err(|e| format!("{}: {}", file_name, e))?;
+    print!("{}", book(&game, moves));
+    Ok(())
+}
+
+// a diagram of some moves of a line
+struct Sheet<'a> {
+    // the stones before the first move
+    start: Vec<u8>,
+    // the numbered stones
+    stones: Vec<(usize, GoColor, i32)>,
+    numbers: Vec<Option<i32>>,
+    // "12 at 5" for moves on points that already have a stone
+    notes: Vec<String>,
+    comments: Vec<(Option<i32>, String)>,
+    range: Option<(i32, i32)>,
+    // the position before each alternative move and the move number it has
+    variations: Vec<(Position, &'a GameNode, i32)>,
+}
+
+impl<'a> Sheet<'a> {
+    fn new(position: &Position) -> Sheet<'a> {
+        Sheet {
+            start: position.board.clone(),
+            stones: vec![],
+            numbers: vec![None; position.board.len()],
+            notes: vec![],
+            comments: vec![],
+            range: None,
+            variations: vec![],
+        }
+    }
+}
+
+struct Book {
+    out: String,
+    width: usize,
+    height: usize,
+    moves: usize,
+    diagrams: usize,
+}
+
+pub fn book(game: &Model, moves: usize) -> String {
+    let info = &game.info;
+    let player = |name: &Option<String>, rank: &Option<String>, color: &str| {
+        let mut s = escape(name.as_ref().map_or(color, |n| n.as_str()));
+        if let Some(ref rank) = *rank {
+            s.push_str(&format!(" ({})", escape(rank)));
+        }
+        s
+    };
+    let mut out = String::from("\\documentclass{article}\n\\usepackage{igo}\n\\begin{document}\n");
+    out.push_str(&format!(
+        "\\section*{{{} -- {}}}\n",
+        player(&info.white, &info.white_rank, "White"),
+        player(&info.black, &info.black_rank, "Black")
+    ));
+    let mut details = vec![];
+    for value in &[&info.event, &info.date, &info.place] {
+        if let Some(ref value) = **value {
+            details.push(escape(value));
+        }
+    }
+    if let Some(komi) = info.komi {
+        details.push(format!("komi {}", komi));
+    }
+    if let Some(handicap) = info.handicap {
+        details.push(format!("handicap {}", handicap));
+    }
+    if let Some(ref result) = info.result {
+        details.push(format!("result {}", escape(result)));
+    }
+    if !details.is_empty() {
+        out.push_str(&format!("\\noindent {}\n\n", details.join(", ")));
+    }
+    out.push_str(&format!("\\gobansize{{{}}}\n", game.width.max(game.height)));
+
+    let mut book = Book {
+        out,
+        width: game.width,
+        height: game.height,
+        moves,
+        diagrams: 0,
+    };
+    let position = Position::new(game.width, game.height);
+    line(&mut book, position, &game.node, None, 0);
+    book.out.push_str("\\end{document}\n");
+    book.out
+}
+
+// the diagrams of the line from the node, following the first child, the
+// variation is the move number the line is an alternative to
+fn line(
+    book: &mut Book,
+    mut position: Position,
+    node: &GameNode,
+    variation: Option<i32>,
+    offset: i32,
+) {
+    let mut sheet = Sheet::new(&position);
+    let mut moves = 0;
+    let mut node = Some(node);
+    while let Some(current) = node {
+        let instructions = collect_moves(current, &[]);
+        let setup = instructions.iter().any(|i| matches!(*i, Setup(_, _)));
+        if moves == book.moves || (setup && moves > 0) {
+            write_sheet(book, sheet, variation);
+            sheet = Sheet::new(&position);
+            moves = 0;
+        }
+
+        let mut number = None;
+        for instruction in &instructions {
+            position.apply(instruction);
+            match *instruction {
+                Move((x, y), color) if x < book.width && y < book.height => {
+                    let n = position.move_number - offset;
+                    let pos = y * book.width + x;
+                    match sheet.numbers[pos] {
+                        Some(first) => sheet.notes.push(format!("{} at {}", n, first)),
+                        None if sheet.start[pos] != 0 => sheet.notes.push(format!(
+                            "{} at {}",
+                            n,
+                            format_point(x, y, book.height)
+                        )),
+                        None => {
+                            sheet.numbers[pos] = Some(n);
+                            sheet.stones.push((pos, color, n));
+                        }
+                    }
+                    number = Some(n);
+                }
+                Move(_, _) | Pass(_) => {
+                    let n = position.move_number - offset;
+                    sheet.notes.push(format!("{} pass", n));
+                    number = Some(n);
+                }
+                _ => {}
+            }
+        }
+        if let Some(n) = number {
+            moves += 1;
+            sheet.range = Some(sheet.range.map_or((n, n), |(first, _)| (first, n)));
+        }
+        if let Ok(comment) = current.get_text("C") {
+            sheet.comments.push((number, comment));
+        }
+        for child in current.children.iter().skip(1) {
+            sheet
+                .variations
+                .push((position.clone(), child, position.move_number + 1 - offset));
+        }
+        node = current.children.first();
+    }
+    if moves > 0 || !sheet.comments.is_empty() || !sheet.variations.is_empty() {
+        write_sheet(book, sheet, variation);
+    }
+}
+
+fn write_sheet(book: &mut Book, sheet: Sheet, variation: Option<i32>) {
+    book.diagrams += 1;
+    let point = |pos: usize| {
+        let (x, y) = (pos % book.width, pos / book.width);
+        format_point(x, y, book.height).to_lowercase()
+    };
+    let mut out = String::from("\\cleargoban\n");
+    for &(stone, command) in &[(2, "black"), (1, "white")] {
+        let points: Vec<String> = (0..sheet.start.len())
+            .filter(|&pos| sheet.start[pos] == stone)
+            .map(&point)
+            .collect();
+        if !points.is_empty() {
+            out.push_str(&format!("\\{}{{{}}}\n", command, points.join(",")));
+        }
+    }
+    for &(pos, color, n) in &sheet.stones {
+        let command = if color == GoColor::Black {
+            "black"
+        } else {
+            "white"
+        };
+        out.push_str(&format!("\\{}[{}]{{{}}}\n", command, n, point(pos)));
+    }
+
+    let mut title = format!("Diagram {}", book.diagrams);
+    if let Some(n) = variation {
+        title.push_str(&format!(", variation for {}", n));
+    }
+    if let Some((first, last)) = sheet.range {
+        if first == last {
+            title.push_str(&format!(" ({})", first));
+        } else {
+            title.push_str(&format!(" ({}--{})", first, last));
+        }
+    }
+    out.push_str(&format!(
+        "\\begin{{center}}\n\\showfullgoban\\\\\n{}",
+        title
+    ));
+    if !sheet.notes.is_empty() {
+        out.push_str(&format!("\\\\\n{}", sheet.notes.join(", ")));
+    }
+    out.push_str("\n\\end{center}\n");
+    for (number, comment) in &sheet.comments {
+        let text = comment
+            .split("\n\n")
+            .map(|paragraph| escape(paragraph.trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        match *number {
+            Some(n) => out.push_str(&format!("\\noindent\\textbf{{{}}}: {}\n\n", n, text)),
+            None => out.push_str(&format!("\\noindent {}\n\n", text)),
+        }
+    }
+    book.out.push_str(&out);
+
+    // the moves of variations are numbered from 1
+    for (position, node, n) in sheet.variations {
+        let offset = position.move_number;
+        line(book, position, node, Some(n), offset);
+    }
+}
+
+// text with the characters LaTeX treats specially replaced
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model;
+
+    #[test]
+    fn test_book() {
+        let game = model(
+            "(;GM[1]SZ[9]PB[Honinbo]PW[Go & Co]C[50% done]\
+             ;B[aa];W[ba];B[ee];W[ab]C[W captures 1](;B[aa]C[3 at 1];W[ca])(;B[ca]))",
+        );
+        let tex = book(&game, 10);
+        assert!(tex.contains("\\section*{Go \\& Co -- Honinbo}"));
+        assert!(tex.contains("\\noindent 50\\% done"));
+        assert!(tex.contains("\\black[1]{a9}\n\\white[2]{b9}\n\\black[3]{e5}\n\\white[4]{a8}\n"));
+        // white 4 took black 1, black 5 is played on that point
+        assert!(tex.contains("Diagram 1 (1--6)\\\\\n5 at 1\n"));
+        assert!(tex.contains("\\textbf{5}: 3 at 1"));
+        // the moves of the variation are numbered from 1
+        assert!(tex.contains("\\black{e5}\n\\white{b9,a8}\n\\black[1]{c9}\n"));
+        assert!(tex.contains("Diagram 2, variation for 5 (1)"));
+
+        let tex = book(&game, 3);
+        assert!(tex.contains("Diagram 2 (4--6)"));
+    }
+}
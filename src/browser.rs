@@ -0,0 +1,291 @@
+// Choosing a game from a list of files: the games in a directory with the
+// players, date and result of each, or the games of a zip archive. The
+// chosen game opens in the navigator and the list is back after quitting.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use termion::clear;
+use termion::event::Key;
+use termion::input::TermRead;
+
+use {
+    archive, get_game_info, import, load_collection, load_root, navigate, parse_collection,
+    read_file,
+};
+
+// the files the navigator can open, by their name
+pub fn is_game_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let name = name.trim_end_matches(".gz");
+    name.ends_with(".sgf") || import::detect(name, &[]).is_some()
+}
+
+fn show_list(title: &str, items: &[String], selected: usize, message: &Option<String>) {
+    println!("{}", clear::All);
+    println!("{}", title);
+    // the part of the list around the selected item that fits the terminal
+    let rows = termion::terminal_size()
+        .map(|(_, height)| (height as usize).saturating_sub(6).max(5))
+        .unwrap_or(20);
+    let first = selected
+        .saturating_sub(rows / 2)
+        .min(items.len().saturating_sub(rows));
+    for (i, item) in items.iter().enumerate().skip(first).take(rows) {
+        let marker = if i == selected { '>' } else { ' ' };
+        println!("{} {}", marker, item);
+    }
+    println!(
+        "{} of {}, w/s: previous/next, p: open, q: back",
+        selected + 1,
+        items.len()
+    );
+    if let Some(ref m) = *message {
+        println!("{}", m);
+    }
+}
+
+// the item chosen from the list, None when the list is left
+pub fn pick(
+    title: &str,
+    items: &[String],
+    selected: &mut usize,
+    message: Option<String>,
+    input: &mut dyn Read,
+) -> Option<usize> {
+    show_list(title, items, *selected, &message);
+    for key in input.keys() {
+        match key.ok()? {
+            Key::Char('w') | Key::Up if *selected > 0 => *selected -= 1,
+            Key::Char('s') | Key::Down if *selected + 1 < items.len() => *selected += 1,
+            Key::Char('p') => return Some(*selected),
+            Key::Char('q') => return None,
+            _ => continue,
+        }
+        show_list(title, items, *selected, &None);
+    }
+    None
+}
+
+// lists the games in a zip archive and opens the chosen ones
+pub fn browse_archive(file_name: &str, data: &[u8], input: &mut dyn Read) -> Result<(), String> {
+    let entries: Vec<_> = archive::entries(data)?
+        .into_iter()
+        .filter(|entry| is_game_file(&entry.name))
+        .collect();
+    if entries.is_empty() {
+        return Err(format!("{} contains no game", file_name));
+    }
+    let names: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+    let mut selected = 0;
+    let mut message = None;
+    while let Some(i) = pick(file_name, &names, &mut selected, message.take(), input) {
+        let entry = &entries[i];
+        // "-/game.sgf" for games of an archive read from stdin
+        let name = format!("{}/{}", file_name, entry.name);
+        match archive::extract(data, entry).and_then(|bytes| parse_collection(&name, bytes)) {
+            Ok(collection) => navigate(collection, &name, 0, input),
+            Err(why) => message = Some(why),
+        }
+    }
+    Ok(())
+}
+
+#[derive(PartialEq, PartialOrd, Eq, Ord)]
+pub enum Kind {
+    Directory,
+    Archive,
+    Game,
+}
+
+// the subdirectories, zip archives and games of a directory, hidden files
+// are left out
+pub fn list_directory(dir: &Path) -> Result<Vec<(Kind, PathBuf)>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|why| format!("couldn't read {}: {}", dir.display(), why))?;
+    let mut items = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|why| format!("couldn't read {}: {}", dir.display(), why))?
+            .path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            items.push((Kind::Directory, path));
+        } else if name.ends_with(".zip") {
+            items.push((Kind::Archive, path));
+        } else if is_game_file(&name) {
+            items.push((Kind::Game, path));
+        }
+    }
+    items.sort();
+    Ok(items)
+}
+
+// the name, players, date and result of an item, only the root node of a game
+// is read
+fn columns(item: &(Kind, PathBuf)) -> [String; 5] {
+    let (ref kind, ref path) = *item;
+    let mut name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let mut row = [
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    ];
+    match *kind {
+        Kind::Directory => name.push('/'),
+        Kind::Archive => row[1] = "archive".to_string(),
+        Kind::Game => match load_root(&path.to_string_lossy()) {
+            Ok(root) => {
+                let info = get_game_info(&root);
+                let player = |name: Option<String>, rank: Option<String>| {
+                    let name = name.unwrap_or_else(|| "?".to_string());
+                    match rank {
+                        Some(rank) => format!("{} {}", name, rank),
+                        None => name,
+                    }
+                };
+                row[1] = player(info.black, info.black_rank);
+                row[2] = player(info.white, info.white_rank);
+                row[3] = info.date.unwrap_or_default();
+                row[4] = info.result.unwrap_or_default();
+            }
+            // the error is shown when the game is opened
+            Err(_) => row[1] = "unreadable".to_string(),
+        },
+    }
+    row[0] = name;
+    row
+}
+
+// the heading and the lines of the items with the columns aligned, long
+// columns are cut
+pub fn table(rows: &[[String; 5]]) -> (String, Vec<String>) {
+    let heading = ["", "Black", "White", "Date", "Result"];
+    let mut widths = [0, 5, 5, 4, 6];
+    for row in rows {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = column.chars().count().min(30).max(*width);
+        }
+    }
+    let line = |row: &[&str]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(column, &width)| {
+                format!("{:<width$}", column.chars().take(width).collect::<String>())
+            })
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    };
+    let lines = rows
+        .iter()
+        .map(|row| line(&row.iter().map(|c| c.as_str()).collect::<Vec<_>>()))
+        .collect();
+    (line(&heading), lines)
+}
+
+// lists the games in a directory, subdirectories and archives open as lists
+// of their own
+pub fn browse_directory(dir: &Path, input: &mut dyn Read) -> Result<(), String> {
+    let items = list_directory(dir)?;
+    if items.is_empty() {
+        return Err(format!("{} contains no games", dir.display()));
+    }
+    let mut rows: Vec<[String; 5]> = items.iter().map(columns).collect();
+    let mut selected = 0;
+    let mut message = None;
+    loop {
+        let (heading, lines) = table(&rows);
+        let title = format!("{}\n  {}", dir.display(), heading);
+        let i = match pick(&title, &lines, &mut selected, message.take(), input) {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        let (ref kind, ref path) = items[i];
+        let name = path.to_string_lossy();
+        let result = match *kind {
+            Kind::Directory => browse_directory(path, input),
+            Kind::Archive => read_file(&name).and_then(|data| browse_archive(&name, &data, input)),
+            Kind::Game => load_collection(&name).map(|collection| {
+                navigate(collection, &name, 0, input);
+                // the game may have been edited
+                rows[i] = columns(&items[i]);
+            }),
+        };
+        message = result.err();
+    }
+}
+
+// lists games found elsewhere, like the results of a search, by their file
+// names, their index in the collection and the columns shown for them
+pub fn browse_games(title: &str, games: &[(String, usize, [String; 5])], input: &mut dyn Read) {
+    let rows: Vec<[String; 5]> = games.iter().map(|game| game.2.clone()).collect();
+    let (heading, lines) = table(&rows);
+    let title = format!("{}\n  {}", title, heading);
+    let mut selected = 0;
+    let mut message = None;
+    while let Some(i) = pick(&title, &lines, &mut selected, message.take(), input) {
+        let (ref name, index, _) = games[i];
+        message = load_collection(name)
+            .map(|collection| navigate(collection, name, index, input))
+            .err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        assert!(is_game_file("games/Game.SGF.gz"));
+        assert!(is_game_file("a.ugi"));
+        assert!(!is_game_file("readme.txt"));
+
+        let row = |columns: [&str; 5]| {
+            let mut row = [
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ];
+            for (cell, column) in row.iter_mut().zip(columns.iter()) {
+                *cell = column.to_string();
+            }
+            row
+        };
+        let (heading, lines) = table(&[
+            row(["2001/", "", "", "", ""]),
+            row([
+                "ear.sgf",
+                "Honinbo Shusaku",
+                "Gennan Inseki",
+                "1846-09-11",
+                "B+2",
+            ]),
+        ]);
+        assert_eq!(
+            heading,
+            "         Black            White          Date        Result"
+        );
+        assert_eq!(lines[0], "2001/");
+        assert_eq!(
+            lines[1],
+            "ear.sgf  Honinbo Shusaku  Gennan Inseki  1846-09-11  B+2"
+        );
+    }
+}
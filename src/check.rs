@@ -0,0 +1,437 @@
+// Checking game records for everything the replay can find wrong: illegal
+// moves, misplaced properties, bad coordinates and the like.
+
+use game_tree::GameNode;
+use json::json_string;
+use Instruction::*;
+use {
+    collect_moves, load_collection, position_to_str, GoColor, PointSt, Position,
+    GAME_INFO_PROPERTIES, MOVE_ANNOTATIONS, MOVE_PROPERTIES, POINT_LISTS, ROOT_PROPERTIES,
+    SETUP_PROPERTIES,
+};
+
+const KNOWN_PROPERTIES: [&str; 67] = [
+    "AB", "AE", "AN", "AP", "AR", "AW", "B", "BL", "BM", "BR", "BT", "C", "CA", "CP", "CR", "DD",
+    "DM", "DO", "DT", "EV", "FF", "FG", "GB", "GC", "GM", "GN", "GW", "HA", "HO", "IT", "KM", "KO",
+    "LB", "LN", "MA", "MN", "N", "OB", "ON", "OT", "OW", "PB", "PC", "PL", "PM", "PW", "RE", "RO",
+    "RU", "SL", "SO", "SQ", "ST", "SZ", "TB", "TE", "TM", "TR", "TW", "UC", "US", "V", "VW", "W",
+    "WL", "WR", "WT",
+];
+
+#[derive(Debug, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+struct Problem {
+    game: usize,
+    path: Vec<usize>,
+    severity: Severity,
+    property: String,
+    message: String,
+}
+
+struct Report {
+    game: usize,
+    problems: Vec<Problem>,
+}
+
+impl Report {
+    fn add(&mut self, path: &[usize], severity: Severity, property: &str, message: String) {
+        self.problems.push(Problem {
+            game: self.game,
+            path: path.to_vec(),
+            severity,
+            property: property.to_string(),
+            message,
+        })
+    }
+}
+
+// what is known while walking down a variation
+struct State<'a> {
+    node: &'a GameNode,
+    path: Vec<usize>,
+    position: Position,
+    // the board before the last move, to find ko violations
+    before_last: Option<Vec<u8>>,
+    game_info: bool,
+}
+
+pub fn check_file(args: &[String]) -> Result<(), String> {
+    let (json, file_name) = match args {
+        [file_name] => (false, file_name),
+        [option, file_name] if option == "--json" => (true, file_name),
+        _ => return Err("Usage: sgf-navigator check [--json] file".to_string()),
+    };
+    let collection = load_collection(file_name)?;
+    let mut problems = vec![];
+    for (i, game) in collection.games.iter().enumerate() {
+        problems.extend(check_game(i + 1, game));
+    }
+
+    if json {
+        println!("{}", to_json(file_name, &problems));
+    } else {
+        for p in &problems {
+            println!(
+                "{}: game {}, node {:?}: {}: {}: {}",
+                file_name,
+                p.game,
+                p.path,
+                if p.severity == Severity::Error {
+                    "error"
+                } else {
+                    "warning"
+                },
+                p.property,
+                p.message
+            );
+        }
+    }
+    match problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count()
+    {
+        0 => Ok(()),
+        1 => Err("1 error".to_string()),
+        n => Err(format!("{} errors", n)),
+    }
+}
+
+fn check_game(game: usize, root: &GameNode) -> Vec<Problem> {
+    let mut report = Report {
+        game,
+        problems: vec![],
+    };
+    check_tree(root, &mut report);
+    report.problems
+}
+
+fn check_tree(root: &GameNode, report: &mut Report) {
+    match root.get_number("GM") {
+        Ok(1) => {}
+        Ok(n) => {
+            report.add(&[], Severity::Error, "GM", format!("game {} is not Go", n));
+            return;
+        }
+        Err(_) => report.add(
+            &[],
+            Severity::Warning,
+            "GM",
+            "missing, Go is assumed".to_string(),
+        ),
+    }
+    let size = root
+        .get_number("SZ")
+        .map(|size| (size, size))
+        .or_else(|_| root.get_number_number("SZ"));
+    let (width, height) = match size {
+        Ok((w, h)) if w > 0 && h > 0 && w <= 52 && h <= 52 => (w as usize, h as usize),
+        Ok(_) => {
+            report.add(&[], Severity::Error, "SZ", "invalid board size".to_string());
+            return;
+        }
+        Err(_) if root.get_points("SZ").is_ok() => {
+            report.add(&[], Severity::Error, "SZ", "invalid board size".to_string());
+            return;
+        }
+        Err(_) => {
+            report.add(
+                &[],
+                Severity::Warning,
+                "SZ",
+                "missing, 19x19 is assumed".to_string(),
+            );
+            (19, 19)
+        }
+    };
+
+    let mut stack = vec![State {
+        node: root,
+        path: vec![],
+        position: Position::new(width, height),
+        before_last: None,
+        game_info: false,
+    }];
+    while let Some(mut state) = stack.pop() {
+        let node = state.node;
+        let path = &state.path;
+        let ids = node.property_ids();
+
+        for (i, id) in ids.iter().enumerate() {
+            if ids[..i].contains(id) {
+                report.add(path, Severity::Error, id, "duplicate property".to_string());
+            } else if !KNOWN_PROPERTIES.contains(id) {
+                report.add(path, Severity::Warning, id, "unknown property".to_string());
+            }
+            if !path.is_empty() && ROOT_PROPERTIES.contains(id) {
+                report.add(
+                    path,
+                    Severity::Error,
+                    id,
+                    "root property in another node".to_string(),
+                );
+            }
+        }
+        if ids.iter().any(|id| GAME_INFO_PROPERTIES.contains(id)) {
+            if state.game_info {
+                let id = ids
+                    .iter()
+                    .find(|id| GAME_INFO_PROPERTIES.contains(id))
+                    .unwrap();
+                report.add(
+                    path,
+                    Severity::Error,
+                    id,
+                    "second game-info node".to_string(),
+                );
+            }
+            state.game_info = true;
+        }
+        let has_move = ids.iter().any(|id| MOVE_PROPERTIES.contains(id));
+        if has_move {
+            if let Some(id) = ids.iter().find(|id| SETUP_PROPERTIES.contains(id)) {
+                report.add(
+                    path,
+                    Severity::Error,
+                    id,
+                    "setup mixed with a move".to_string(),
+                );
+            }
+        } else if let Some(id) = ids.iter().find(|id| MOVE_ANNOTATIONS.contains(id)) {
+            report.add(
+                path,
+                Severity::Warning,
+                id,
+                "move annotation without a move".to_string(),
+            );
+        }
+
+        // the replay can only handle valid coordinates
+        let mut valid = true;
+        for id in &ids {
+            if let Err(message) = check_points(node, id, width, height) {
+                report.add(path, Severity::Error, id, message);
+                valid = false;
+            }
+        }
+        if valid {
+            replay_node(&mut state, report);
+        }
+
+        for (i, child) in node.children.iter().enumerate().rev() {
+            let mut child_path = state.path.clone();
+            child_path.push(i);
+            stack.push(State {
+                node: child,
+                path: child_path,
+                position: state.position.clone(),
+                before_last: state.before_last.clone(),
+                game_info: state.game_info,
+            });
+        }
+    }
+}
+
+// plays the moves and setup of the node, reporting what is not allowed
+fn replay_node(state: &mut State, report: &mut Report) {
+    let path = &state.path;
+    let position = &mut state.position;
+    let forced = state.node.get_points("KO").is_ok();
+    for instr in collect_moves(state.node, &[]) {
+        match instr {
+            Setup((x, y), ref st) => {
+                let pos = y * position.width + x;
+                let id = match *st {
+                    PointSt::Black => "AB",
+                    PointSt::White => "AW",
+                    PointSt::Free => "AE",
+                };
+                match (id, position.board[pos]) {
+                    ("AE", 0) => report.add(
+                        path,
+                        Severity::Warning,
+                        id,
+                        format!("{} is empty", point(x, y)),
+                    ),
+                    ("AE", _) | (_, 0) => {}
+                    _ => report.add(
+                        path,
+                        Severity::Warning,
+                        id,
+                        format!("{} is occupied", point(x, y)),
+                    ),
+                }
+                state.before_last = None;
+            }
+            Move((x, y), color) if x < position.width && y < position.height => {
+                let id = if color == GoColor::Black { "B" } else { "W" };
+                let pos = y * position.width + x;
+                if position.board[pos] != 0 && !forced {
+                    report.add(
+                        path,
+                        Severity::Error,
+                        id,
+                        format!("{} is occupied", point(x, y)),
+                    );
+                }
+                let before = position.board.clone();
+                let removed = position.apply(&instr);
+                if removed.contains(&pos) && !forced {
+                    report.add(
+                        path,
+                        Severity::Warning,
+                        id,
+                        format!("suicide at {}", point(x, y)),
+                    );
+                }
+                if state.before_last.as_ref() == Some(&position.board) && !forced {
+                    report.add(
+                        path,
+                        Severity::Error,
+                        id,
+                        format!("ko violation at {}", point(x, y)),
+                    );
+                }
+                state.before_last = Some(before);
+                continue;
+            }
+            Move(_, _) | Pass(_) => state.before_last = Some(position.board.clone()),
+            _ => {}
+        }
+        position.apply(&instr);
+    }
+}
+
+fn point(x: usize, y: usize) -> String {
+    position_to_str(x, y)
+}
+
+// checks the format of point values and that they are on the board
+pub fn check_points(node: &GameNode, id: &str, width: usize, height: usize) -> Result<(), String> {
+    let values = node.get_points(id).unwrap_or_default();
+    let on_board = |p: &str| -> Result<(usize, usize), String> {
+        let mut chars = p.chars();
+        let coordinate = |c: Option<char>| {
+            c.and_then(|c| "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ".find(c))
+        };
+        match (
+            coordinate(chars.next()),
+            coordinate(chars.next()),
+            chars.next(),
+        ) {
+            (Some(x), Some(y), None) if x < width && y < height => Ok((x, y)),
+            (Some(_), Some(_), None) => Err(format!("{} is not on the board", p)),
+            _ => Err(format!("{} is not a point", p)),
+        }
+    };
+    match id {
+        "B" | "W" => {
+            let value = values.first().map(|v| v.as_str()).unwrap_or("");
+            if value.is_empty() || (value == "tt" && width <= 19 && height <= 19) {
+                return Ok(());
+            }
+            on_board(value).map(|_| ())
+        }
+        "AR" | "LN" | "LB" => {
+            for value in &values {
+                let mut parts = value.splitn(2, ':');
+                let first = parts.next().unwrap_or("");
+                let second = parts
+                    .next()
+                    .ok_or(format!("{} is not a composed value", value))?;
+                on_board(first)?;
+                if id != "LB" && on_board(second)? == on_board(first)? {
+                    return Err(format!("{} starts and ends at the same point", value));
+                }
+            }
+            Ok(())
+        }
+        _ if POINT_LISTS.contains(&id) => {
+            for value in &values {
+                if value.is_empty() && id == "VW" {
+                    continue;
+                }
+                let mut corners = value.splitn(2, ':');
+                on_board(corners.next().unwrap_or(""))?;
+                if let Some(corner) = corners.next() {
+                    on_board(corner)?;
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn to_json(file_name: &str, problems: &[Problem]) -> String {
+    let problems: Vec<String> = problems
+        .iter()
+        .map(|p| {
+            let path: Vec<String> = p.path.iter().map(|i| i.to_string()).collect();
+            format!(
+                "{{\"game\": {}, \"path\": [{}], \"severity\": {}, \"property\": {}, \"message\": {}}}",
+                p.game,
+                path.join(", "),
+                json_string(if p.severity == Severity::Error { "error" } else { "warning" }),
+                json_string(&p.property),
+                json_string(&p.message)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"file\": {}, \"problems\": [{}]}}",
+        json_string(file_name),
+        problems.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_tree::Collection;
+
+    #[test]
+    fn test_check() {
+        let c = Collection::from_sgf(
+            "(;GM[1]SZ[4]XX[1]AB[ba][ab][bc]AW[ca][bb][db][cc]\
+             (;B[cb];W[bb])(;B[bb])(;B[zz])(;W[dd]AB[ad])(;B[aa]C[x]C[y])(;BM[1])\
+             (;AB[ac][bd];W[ad])(;AB[bb])(;PB[x];PW[y]))",
+        )
+        .unwrap();
+        let problems: Vec<(Vec<usize>, &str, String)> = check_game(1, &c.games[0])
+            .into_iter()
+            .map(|p| {
+                (
+                    p.path,
+                    if p.severity == Severity::Error {
+                        "E"
+                    } else {
+                        "W"
+                    },
+                    p.message,
+                )
+            })
+            .collect();
+        let expected = vec![
+            (vec![], "W", "unknown property"),
+            (vec![0, 0], "E", "ko violation at bb"),
+            (vec![1], "E", "bb is occupied"),
+            (vec![2], "E", "zz is not on the board"),
+            (vec![3], "E", "setup mixed with a move"),
+            (vec![4], "E", "duplicate property"),
+            (vec![5], "W", "move annotation without a move"),
+            (vec![6, 0], "W", "suicide at ad"),
+            (vec![7], "W", "bb is occupied"),
+            (vec![8, 0], "E", "second game-info node"),
+        ];
+        let expected: Vec<(Vec<usize>, &str, String)> = expected
+            .into_iter()
+            .map(|(path, severity, message)| (path, severity, message.to_string()))
+            .collect();
+        assert_eq!(problems, expected);
+    }
+}
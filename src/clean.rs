@@ -0,0 +1,227 @@
+// Normalizing game records: FF[3] conventions are converted to FF[4], point
+// lists are sorted, empty nodes and duplicate variations are removed.
+
+use std::io::prelude::*;
+use std::io;
+
+use game_tree::GameNode;
+use {load_collection, point_list, write_point_list, POINT_LISTS};
+
+// properties of FF[3] that were dropped in FF[4]
+const OBSOLETE_PROPERTIES: [&str; 15] = [
+    "BS", "CH", "EL", "EX", "ID", "LT", "OM", "OP", "OV", "RG", "SC", "SE", "SI", "TC", "WS",
+];
+
+// what was changed in a game
+#[derive(Default)]
+struct Changes {
+    passes: usize,
+    renamed: usize,
+    obsolete: usize,
+    sorted: usize,
+    empty_nodes: usize,
+    duplicates: usize,
+}
+
+// cleans all games of a file and writes them to stdout, the report of the
+// changes goes to stderr
+pub fn clean_file(args: &[String]) -> Result<(), String> {
+    let file_name = match args {
+        [file_name] => file_name,
+        _ => return Err("Usage: sgf-navigator clean file".to_string()),
+    };
+    let mut collection = load_collection(file_name)?;
+    for (i, game) in collection.games.iter_mut().enumerate() {
+        for line in clean(game) {
+            eprintln!("{}: game {}: {}", file_name, i + 1, line);
+        }
+    }
+    io::stdout()
+        .write_all(collection.to_string().as_bytes())
+        .map_err(|why| format!("couldn't write the cleaned file: {}", why))
+}
+
+// cleans a game and returns a report of the changes
+fn clean(root: &mut GameNode) -> Vec<String> {
+    let mut report = vec![];
+    let mut changes = Changes::default();
+
+    let format = root.get_number("FF").unwrap_or(1);
+    if format < 4 {
+        root.set_property("FF", vec!["4".to_string()]);
+        report.push(format!("converted FF[{}] to FF[4]", format));
+    }
+    if let Ok(charset) = root.get_simple_text("CA") {
+        if !charset.trim().eq_ignore_ascii_case("UTF-8") {
+            root.set_simple_text("CA", "UTF-8");
+            report.push(format!("re-encoded text from {} as UTF-8", charset));
+        }
+    }
+    let (width, height) = root
+        .get_number("SZ")
+        .map(|size| (size, size))
+        .or_else(|_| root.get_number_number("SZ"))
+        .unwrap_or((19, 19));
+    let tt_is_pass = width <= 19 && height <= 19;
+
+    let mut nodes = vec![&mut *root];
+    while let Some(node) = nodes.pop() {
+        node.normalize();
+        convert_node(node, tt_is_pass, &mut changes);
+        nodes.extend(node.children.iter_mut());
+    }
+    remove_empty_nodes(root, &mut changes);
+    remove_duplicates(root, &mut changes);
+
+    let counts = [
+        (changes.passes, "tt passes written as []"),
+        (changes.renamed, "FF[3] markup properties renamed"),
+        (changes.obsolete, "obsolete properties removed"),
+        (changes.sorted, "point lists sorted or compressed"),
+        (changes.empty_nodes, "empty nodes removed"),
+        (changes.duplicates, "duplicate variations removed"),
+    ];
+    for &(count, what) in &counts {
+        if count > 0 {
+            report.push(format!("{} {}", count, what));
+        }
+    }
+    report
+}
+
+fn convert_node(node: &mut GameNode, tt_is_pass: bool, changes: &mut Changes) {
+    for id in &["B", "W"] {
+        if tt_is_pass && node.get_point(id).ok().as_deref() == Some("tt") {
+            node.set_point(id, String::new());
+            changes.passes += 1;
+        }
+    }
+
+    // L and M were labels and marks
+    if let Ok(points) = node.get_points("L") {
+        node.remove_property("L");
+        let mut labels = node.get_points("LB").unwrap_or_default();
+        for (point, letter) in points.iter().zip(b'A'..=b'Z') {
+            labels.push(format!("{}:{}", point, letter as char));
+        }
+        node.set_property("LB", labels);
+        changes.renamed += 1;
+    }
+    if let Some(points) = node.remove_property("M") {
+        let mut marks = node.get_points("MA").unwrap_or_default();
+        marks.extend(points);
+        node.set_property("MA", marks);
+        changes.renamed += 1;
+    }
+    for id in &OBSOLETE_PROPERTIES {
+        if node.remove_property(id).is_some() {
+            changes.obsolete += 1;
+        }
+    }
+
+    for id in &POINT_LISTS {
+        if let Ok(values) = node.get_points(id) {
+            // VW[] shows the whole board again
+            if values.iter().any(|value| value.is_empty()) {
+                continue;
+            }
+            let sorted = write_point_list(&point_list(node, id), true);
+            if sorted != values {
+                node.set_property(id, sorted);
+                changes.sorted += 1;
+            }
+        }
+    }
+    for id in &["LB", "AR", "LN"] {
+        if let Ok(mut values) = node.get_points(id) {
+            let before = values.clone();
+            values.sort();
+            values.dedup();
+            if values != before {
+                node.set_property(id, values);
+                changes.sorted += 1;
+            }
+        }
+    }
+}
+
+// replaces nodes without properties by their children
+fn remove_empty_nodes(node: &mut GameNode, changes: &mut Changes) {
+    let mut i = 0;
+    while i < node.children.len() {
+        if node.children[i].property_ids().is_empty() {
+            let empty = node.children.remove(i);
+            for (j, child) in empty.children.into_iter().enumerate() {
+                node.children.insert(i + j, child);
+            }
+            changes.empty_nodes += 1;
+        } else {
+            i += 1;
+        }
+    }
+    for child in &mut node.children {
+        remove_empty_nodes(child, changes);
+    }
+}
+
+// removes variations that are the same as an earlier sibling
+fn remove_duplicates(node: &mut GameNode, changes: &mut Changes) {
+    let mut i = 1;
+    while i < node.children.len() {
+        if node.children[..i].iter().any(|c| same_tree(c, &node.children[i])) {
+            node.children.remove(i);
+            changes.duplicates += 1;
+        } else {
+            i += 1;
+        }
+    }
+    for child in &mut node.children {
+        remove_duplicates(child, changes);
+    }
+}
+
+fn same_tree(a: &GameNode, b: &GameNode) -> bool {
+    let properties = |node: &GameNode| {
+        let mut properties: Vec<(String, Vec<String>)> = node
+            .property_ids()
+            .into_iter()
+            .map(|id| (id.to_string(), node.get_points(id).unwrap()))
+            .collect();
+        properties.sort();
+        properties
+    };
+    properties(a) == properties(b)
+        && a.children.len() == b.children.len()
+        && a.children.iter().zip(&b.children).all(|(a, b)| same_tree(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_tree::Collection;
+
+    #[test]
+    fn test_clean() {
+        let mut c = Collection::from_sgf(
+            "(;FF[3]GM[1]SZ[9]ID[x];B[tt]AB[bb][aa][ab][ba]L[cc][dd];\
+             (;W[cc];B[dd])(;W[cc];B[dd])(;CH[1]))",
+        ).unwrap();
+        let report = clean(&mut c.games[0]);
+        assert_eq!(
+            c.to_string(),
+            "(;FF[4]GM[1]SZ[9];B[]AB[aa:bb]LB[cc:A][dd:B];W[cc];B[dd])"
+        );
+        assert_eq!(
+            report,
+            vec![
+                "converted FF[3] to FF[4]",
+                "1 tt passes written as []",
+                "1 FF[3] markup properties renamed",
+                "2 obsolete properties removed",
+                "1 point lists sorted or compressed",
+                "2 empty nodes removed",
+                "1 duplicate variations removed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,501 @@
+// Deflate compression (RFC 1951) with the fixed Huffman codes and the zlib
+// wrapper (RFC 1950), enough for writing PNG files, and the decompression of
+// any deflate stream for reading gzip files (RFC 1952) and zip archives.
+
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const HASH_SIZE: usize = 1 << 15;
+// how many earlier positions are tried for a match
+const MAX_CHAIN: usize = 64;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    // writes the lowest n bits, the first bit first
+    fn write(&mut self, value: u32, n: u32) {
+        self.bits |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are written with their highest bit first
+    fn write_code(&mut self, code: u32, n: u32) {
+        let mut reversed = 0;
+        for i in 0..n {
+            reversed |= ((code >> i) & 1) << (n - 1 - i);
+        }
+        self.write(reversed, n);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.bits as u8);
+        }
+        self.bytes
+    }
+}
+
+fn write_literal(out: &mut BitWriter, value: usize) {
+    let value = value as u32;
+    match value {
+        0..=143 => out.write_code(0x30 + value, 8),
+        144..=255 => out.write_code(0x190 + value - 144, 9),
+        256..=279 => out.write_code(value - 256, 7),
+        _ => out.write_code(0xc0 + value - 280, 8),
+    }
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base <= length)
+        .unwrap();
+    write_literal(out, 257 + code);
+    out.write((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code]);
+    let code = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base <= distance)
+        .unwrap();
+    out.write_code(code as u32, 5);
+    out.write(
+        (distance - DISTANCE_BASE[code]) as u32,
+        DISTANCE_EXTRA[code],
+    );
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) % HASH_SIZE
+}
+
+// compresses the data into one deflate block with the fixed codes
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter {
+        bytes: vec![],
+        bits: 0,
+        count: 0,
+    };
+    // the last block, fixed Huffman codes
+    out.write(1, 1);
+    out.write(1, 2);
+
+    // the last position of each hash and the previous one with the same hash
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; data.len()];
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + 3 <= data.len() {
+            let h = hash(data, i);
+            let mut candidate = head[h];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let limit = MAX_MATCH.min(data.len() - i);
+                let mut length = 0;
+                while length < limit && data[candidate + length] == data[i + length] {
+                    length += 1;
+                }
+                if length > best.0 {
+                    best = (length, i - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+        let step = if best.0 >= 3 {
+            write_match(&mut out, best.0, best.1);
+            best.0
+        } else {
+            write_literal(&mut out, data[i] as usize);
+            1
+        };
+        for (j, earlier) in previous.iter_mut().enumerate().skip(i).take(step) {
+            if j + 3 <= data.len() {
+                let h = hash(data, j);
+                *earlier = head[h];
+                head[h] = j;
+            }
+        }
+        i += step;
+    }
+    write_literal(&mut out, 256);
+    out.finish()
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+// the data compressed in the zlib format
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    let check = adler32(data);
+    out.extend(&[
+        (check >> 24) as u8,
+        (check >> 16) as u8,
+        (check >> 8) as u8,
+        check as u8,
+    ]);
+    out
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// the data compressed in the gzip format
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend(deflate(data));
+    for value in &[crc32(data), data.len() as u32] {
+        out.extend(&[
+            *value as u8,
+            (*value >> 8) as u8,
+            (*value >> 16) as u8,
+            (*value >> 24) as u8,
+        ]);
+    }
+    out
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+// the largest file that is decompressed, game records are much smaller and
+// anything larger is more likely a zip bomb
+pub const MAX_SIZE: usize = 64 << 20;
+
+// the contents of a gzip file, members written one after the other are
+// joined
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+    let mut start = 0;
+    while start < data.len() {
+        let member = &data[start..];
+        if member.len() < 18 || !is_gzip(member) || member[2] != 8 {
+            return Err("not a gzip file".to_string());
+        }
+        let flags = member[3];
+        let mut i = 10;
+        // extra field, file name, comment and header checksum
+        if flags & 4 != 0 {
+            i += 2 + (member[i] as usize | (member[i + 1] as usize) << 8);
+        }
+        for &flag in &[8, 16] {
+            if flags & flag != 0 {
+                while i < member.len() && member[i] != 0 {
+                    i += 1;
+                }
+                i += 1;
+            }
+        }
+        if flags & 2 != 0 {
+            i += 2;
+        }
+        if i > member.len() {
+            return Err("truncated gzip file".to_string());
+        }
+        let (data, length) = inflate(&member[i..], MAX_SIZE - out.len())?;
+        let trailer = &member[(i + length).min(member.len())..];
+        if trailer.len() < 8 {
+            return Err("truncated gzip file".to_string());
+        }
+        let word =
+            |b: &[u8]| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24;
+        if word(trailer) != crc32(&data) || word(&trailer[4..]) != data.len() as u32 {
+            return Err("corrupt gzip file".to_string());
+        }
+        out.extend(data);
+        start += i + length + 8;
+    }
+    Ok(out)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    // the position in bits
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = *self
+                .data
+                .get(self.position / 8)
+                .ok_or("unexpected end of compressed data")?;
+            value |= ((byte >> (self.position % 8)) as u32 & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// a canonical Huffman code as the number of codes of each length and the
+// symbols ordered by their codes
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = vec![];
+        for length in 1..16 {
+            for (symbol, &l) in lengths.iter().enumerate() {
+                if l as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<usize, String> {
+        // the first code of the length and the index of its symbol
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for length in 1..16 {
+            code |= input.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+    let literals = input.bits(5)? as usize + 257;
+    let distances = input.bits(5)? as usize + 1;
+    let count = input.bits(4)? as usize + 4;
+    let mut lengths = [0; 19];
+    for &symbol in ORDER.iter().take(count) {
+        lengths[symbol] = input.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match code.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or("repeated length without a length")?,
+                3 + input.bits(2)?,
+            ),
+            17 => (0, 3 + input.bits(3)?),
+            _ => (0, 11 + input.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(length);
+        }
+    }
+    if lengths.len() > literals + distances {
+        return Err("too many code lengths".to_string());
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+// decompresses a deflate stream, returns the data and the number of bytes
+// the stream took, data larger than the limit is an error instead of filling
+// the memory
+pub fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), String> {
+    let mut input = BitReader { data, position: 0 };
+    let mut out: Vec<u8> = vec![];
+    let too_large = || Err(format!("the data inflates to more than {} bytes", limit));
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => {
+                input.align();
+                let start = input.position / 8;
+                if start + 4 > data.len() {
+                    return Err("unexpected end of compressed data".to_string());
+                }
+                let length = data[start] as usize | (data[start + 1] as usize) << 8;
+                let end = start + 4 + length;
+                if end > data.len() {
+                    return Err("unexpected end of compressed data".to_string());
+                }
+                if out.len() + length > limit {
+                    return too_large();
+                }
+                out.extend(&data[start + 4..end]);
+                input.position = end * 8;
+            }
+            kind @ 1..=2 => {
+                let (literals, distances) = if kind == 1 {
+                    fixed_codes()
+                } else {
+                    dynamic_codes(&mut input)?
+                };
+                loop {
+                    let symbol = literals.decode(&mut input)?;
+                    if symbol < 256 {
+                        if out.len() == limit {
+                            return too_large();
+                        }
+                        out.push(symbol as u8);
+                        continue;
+                    } else if symbol == 256 {
+                        break;
+                    }
+                    let code = symbol - 257;
+                    if code >= LENGTH_BASE.len() {
+                        return Err("invalid length code".to_string());
+                    }
+                    let length = LENGTH_BASE[code] + input.bits(LENGTH_EXTRA[code])? as usize;
+                    let code = distances.decode(&mut input)?;
+                    if code >= DISTANCE_BASE.len() {
+                        return Err("invalid distance code".to_string());
+                    }
+                    let distance = DISTANCE_BASE[code] + input.bits(DISTANCE_EXTRA[code])? as usize;
+                    if distance > out.len() {
+                        return Err("distance too far back".to_string());
+                    }
+                    if out.len() + length > limit {
+                        return too_large();
+                    }
+                    let start = out.len() - distance;
+                    for i in 0..length {
+                        let byte = out[start + i];
+                        out.push(byte);
+                    }
+                }
+            }
+            _ => return Err("invalid block type".to_string()),
+        }
+        if last {
+            input.align();
+            return Ok((out, input.position / 8));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        // a single literal, the end of block and the padding
+        assert_eq!(deflate(b"a"), vec![0x4b, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn test_inflate() {
+        let data = b"(;GM[1]SZ[19];B[pd];W[dp];B[pp];W[dd];B[pd])".repeat(20);
+        assert_eq!(
+            inflate(&deflate(&data), data.len()).unwrap(),
+            (data.clone(), deflate(&data).len())
+        );
+        assert!(inflate(&deflate(&data), data.len() - 1).is_err());
+        // a megabyte of zeros in a few kilobytes
+        let zeros = deflate(&[0; 1 << 20]);
+        assert!(zeros.len() < 1 << 13);
+        assert_eq!(
+            inflate(&zeros, 1 << 16),
+            Err("the data inflates to more than 65536 bytes".to_string())
+        );
+        assert_eq!(gunzip(&gzip(&data)).unwrap(), data);
+        // a game compressed by zlib with its own Huffman codes
+        let compressed = [
+            0x1d, 0xc8, 0x3b, 0x0a, 0x84, 0x30, 0x14, 0x05, 0xd0, 0xde, 0x55, 0x58, 0x2a, 0x56,
+            0x8a, 0xcd, 0x90, 0x2e, 0xe0, 0xaf, 0x10, 0x64, 0x2c, 0x04, 0x1f, 0xb7, 0x50, 0x13,
+            0x51, 0x84, 0x97, 0x04, 0x71, 0xff, 0x9a, 0x74, 0x87, 0x93, 0x88, 0xa6, 0xa7, 0x1c,
+            0x75, 0x4d, 0x25, 0xc6, 0x99, 0xf2, 0x1f, 0x06, 0x49, 0xad, 0xe1, 0x93, 0x57, 0x13,
+            0x8f, 0xc7, 0x73, 0x2f, 0xd7, 0x83, 0x61, 0xa2, 0x46, 0x33, 0x2f, 0x1c, 0x77, 0x7c,
+            0xeb, 0xeb, 0xc4, 0xbf, 0x22, 0x99, 0x15, 0x88, 0x84, 0x24, 0xa7, 0x20, 0x26, 0x52,
+            0x1b, 0x3e, 0x5b, 0xe7, 0x6d, 0x82, 0x37, 0xeb, 0xed, 0x4c, 0x78, 0xed, 0xad, 0x9d,
+            0xf7, 0x1e, 0x5e, 0x59, 0xa4, 0xd1, 0x0b,
+        ];
+        let (text, length) = inflate(&compressed, MAX_SIZE).unwrap();
+        assert_eq!(length, compressed.len());
+        assert!(String::from_utf8(text)
+            .unwrap()
+            .ends_with(";B[fp];W[dp])\n"));
+        assert!(gunzip(&gzip(&data)[..20]).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+// A position prepared for drawing, the stones, move numbers and markup of a
+// node, and the export subcommand writing it in the different formats.
+
+use std::io;
+use std::io::prelude::*;
+
+use animation;
+use game_tree::{decode_simple_text, GameNode};
+use png;
+use raster;
+use svg;
+use text;
+use {
+    collect_moves, get_board, load_collection, parse_point, point_list, replay, str_to_position,
+    traverse, Model, Numbering,
+};
+
+pub type Point = (usize, usize);
+
+#[derive(Clone)]
+pub enum Mark {
+    Circle,
+    Cross,
+    Square,
+    Triangle,
+    Label(String),
+}
+
+pub struct Diagram {
+    pub width: usize,
+    pub height: usize,
+    // the part of the board that is shown, the corners included
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+
+    pub board: Vec<u8>,
+    pub numbers: Vec<Option<i32>>,
+    pub marks: Vec<Option<Mark>>,
+    pub selected: Vec<bool>,
+    pub dimmed: Vec<bool>,
+    // from, to and whether it is an arrow
+    pub lines: Vec<(Point, Point, bool)>,
+
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub footnotes: Vec<String>,
+}
+
+impl Diagram {
+    // the position at the current node of the game, as shown by show_board
+    pub fn new(game: &Model) -> Result<Diagram, String> {
+        let instructions = collect_moves(&game.node, &game.path);
+        let (position, numbers, footnotes) = replay(game, &instructions);
+        Diagram::at(game, &game.path, position.board, numbers, footnotes)
+    }
+
+    // the markup of the node at the path on a board that is already replayed
+    pub fn at(
+        game: &Model,
+        path: &[usize],
+        board: Vec<u8>,
+        numbers: Vec<Option<i32>>,
+        footnotes: Vec<String>,
+    ) -> Result<Diagram, String> {
+        let (width, height) = (game.width, game.height);
+        let node = traverse(&game.node, path).ok_or(format!("there is no node {:?}", path))?;
+        // the points of labels, arrows and lines, malformed ones and those
+        // off the board are left out
+        let on_board = |point: Option<&str>| match point.and_then(str_to_position) {
+            Some((x, y)) if x < width && y < height => Some((x, y)),
+            _ => None,
+        };
+
+        let mut marks: Vec<Option<Mark>> = (0..width * height).map(|_| None).collect();
+        for &(id, ref mark) in &[
+            ("CR", Mark::Circle),
+            ("MA", Mark::Cross),
+            ("SQ", Mark::Square),
+            ("TR", Mark::Triangle),
+        ] {
+            for (x, y) in point_list(node, id) {
+                if x < width && y < height {
+                    marks[y * width + x] = Some(mark.clone());
+                }
+            }
+        }
+        for label in node.get_points("LB").unwrap_or_default() {
+            let mut parts = label.splitn(2, ':');
+            if let (Some((x, y)), Some(text)) = (on_board(parts.next()), parts.next()) {
+                marks[y * width + x] = Some(Mark::Label(decode_simple_text(text)));
+            }
+        }
+
+        let points = |id: &str, node: &GameNode| {
+            let mut shown = vec![false; width * height];
+            for (x, y) in point_list(node, id) {
+                if x < width && y < height {
+                    shown[y * width + x] = true;
+                }
+            }
+            shown
+        };
+        // DD stays until the next DD on the path
+        let mut dimmed = vec![false; width * height];
+        let mut on_path = &game.node;
+        for depth in 0..path.len() + 1 {
+            if depth > 0 {
+                on_path = &on_path.children[path[depth - 1]];
+            }
+            if on_path.get_points("DD").is_ok() {
+                dimmed = points("DD", on_path);
+            }
+        }
+
+        let mut lines = vec![];
+        for &(id, arrow) in &[("AR", true), ("LN", false)] {
+            for value in node.get_points(id).unwrap_or_default() {
+                let mut parts = value.splitn(2, ':');
+                if let (Some(from), Some(to)) = (on_board(parts.next()), on_board(parts.next())) {
+                    lines.push((from, to, arrow));
+                }
+            }
+        }
+
+        Ok(Diagram {
+            width,
+            height,
+            left: 0,
+            top: 0,
+            right: width - 1,
+            bottom: height - 1,
+            board,
+            numbers,
+            marks,
+            selected: points("SL", node),
+            dimmed,
+            lines,
+            name: node.get_simple_text("N").ok(),
+            comment: node.get_text("C").ok(),
+            footnotes,
+        })
+    }
+
+    // shows only the part of the board between the corners
+    pub fn crop(&mut self, (x0, y0): (usize, usize), (x1, y1): (usize, usize)) {
+        self.left = x0.min(x1);
+        self.right = x0.max(x1);
+        self.top = y0.min(y1);
+        self.bottom = y0.max(y1);
+    }
+
+    pub fn columns(&self) -> usize {
+        self.right - self.left + 1
+    }
+
+    pub fn rows(&self) -> usize {
+        self.bottom - self.top + 1
+    }
+
+    // the hoshi of the usual board sizes
+    pub fn star_points(&self) -> Vec<(usize, usize)> {
+        let lines = match (self.width, self.height) {
+            (19, 19) => vec![3, 9, 15],
+            (13, 13) => vec![3, 9],
+            (9, 9) => vec![2, 6],
+            _ => vec![],
+        };
+        let mut points = vec![];
+        for &y in &lines {
+            for &x in &lines {
+                points.push((x, y));
+            }
+        }
+        // and tengen on the small boards
+        if lines.len() == 2 {
+            points.push((self.width / 2, self.height / 2));
+        }
+        points
+    }
+}
+
+// parses a region like "A19:K10" or "aa:jj"
+pub fn parse_region(s: &str, width: usize, height: usize) -> Option<(Point, Point)> {
+    let mut corners = s.splitn(2, ':');
+    let from = parse_point(corners.next()?, width, height)?;
+    let to = parse_point(corners.next()?, width, height)?;
+    Some((from, to))
+}
+
+fn parse_numbering(s: &str) -> Option<Numbering> {
+    match s {
+        "off" => Some(Numbering::Off),
+        "all" => Some(Numbering::All),
+        "branch" => Some(Numbering::SinceBranch),
+        _ if s.starts_with("last:") => s[5..].parse().ok().map(Numbering::Last),
+        _ => None,
+    }
+}
+
+// how the raster formats are drawn
+pub struct Style {
+    // the width of the image in pixels
+    pub size: usize,
+    pub theme: String,
+    // how long a move of an animation is shown in milliseconds
+    pub delay: usize,
+    // the node names and comments below the frames of an animation
+    pub captions: bool,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            size: 600,
+            theme: "wood".to_string(),
+            delay: 1000,
+            captions: false,
+        }
+    }
+}
+
+// writes the diagram in the format of the file name extension
+pub fn export(diagram: &Diagram, format: &str, style: &Style) -> Result<Vec<u8>, String> {
+    match format {
+        "svg" => Ok(svg::to_svg(diagram).into_bytes()),
+        "sensei" => Ok(text::to_sensei(diagram).into_bytes()),
+        "unicode" => Ok(text::to_unicode(diagram).into_bytes()),
+        "png" => {
+            let theme = raster::theme(&style.theme).ok_or(format!(
+                "unknown theme {}, use {}",
+                style.theme,
+                raster::THEMES.join(", ")
+            ))?;
+            Ok(png::encode(&raster::render(diagram, style.size, &theme)))
+        }
+        _ => Err(format!("unknown format {}", format)),
+    }
+}
+
+// writes the position at the current node of the game, or an animation of
+// the moves up to it
+pub fn export_game(
+    game: &Model,
+    crop: Option<(Point, Point)>,
+    format: &str,
+    style: &Style,
+) -> Result<Vec<u8>, String> {
+    let mut diagrams = if format == "gif" {
+        animation::frames(game, &game.path)?
+    } else {
+        vec![Diagram::new(game)?]
+    };
+    if let Some((from, to)) = crop {
+        for diagram in &mut diagrams {
+            diagram.crop(from, to);
+        }
+    }
+    if format == "gif" {
+        animation::to_gif(&diagrams, style)
+    } else {
+        export(&diagrams[0], format, style)
+    }
+}
+
+// the widest image in pixels, larger ones would take gigabytes
+const MAX_SIZE: usize = 10000;
+
+const USAGE: &str = "Usage: sgf-navigator export svg|png|gif|sensei|unicode file \
+                     [--path 0,0,1] [--crop A19:K10] [--numbers off|all|last:N|branch] \
+                     [--size 600] [--theme wood|flat|print] [--delay 1000] [--captions]";
+
+// the export subcommand, the diagram is written to stdout
+pub fn export_file(args: &[String]) -> Result<(), String> {
+    let format = args.first().ok_or(USAGE)?;
+    let mut file_name = None;
+    let mut path = None;
+    let mut crop = None;
+    let mut numbering = Numbering::Off;
+    let mut style = Style::default();
+    let mut options = args[1..].iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--path" => {
+                let value = options.next().ok_or(USAGE)?;
+                path = Some(
+                    value
+                        .split(',')
+                        .filter(|i| !i.is_empty())
+                        .map(|i| i.trim().parse::<usize>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| format!("invalid path {}", value))?,
+                );
+            }
+            "--crop" => crop = Some(options.next().ok_or(USAGE)?.clone()),
+            "--numbers" => {
+                let value = options.next().ok_or(USAGE)?;
+                numbering = parse_numbering(value).ok_or(format!("invalid numbers {}", value))?;
+            }
+            "--size" => {
+                let value = options.next().ok_or(USAGE)?;
+                style.size = value
+                    .parse()
+                    .ok()
+                    .filter(|&size| size > 0 && size <= MAX_SIZE)
+                    .ok_or(format!(
+                        "invalid size {}, use 1 to {} pixels",
+                        value, MAX_SIZE
+                    ))?;
+            }
+            "--theme" => style.theme = options.next().ok_or(USAGE)?.clone(),
+            "--delay" => {
+                let value = options.next().ok_or(USAGE)?;
+                style.delay = value
+                    .parse()
+                    .map_err(|_| format!("invalid delay {}", value))?;
+            }
+            "--captions" => style.captions = true,
+            _ if file_name.is_none() => file_name = Some(arg.clone()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let file_name = file_name.ok_or(USAGE)?;
+
+    let mut collection = load_collection(&file_name)?;
+    if collection.games.is_empty() {
+        return Err(format!("{} contains no game", file_name));
+    }
+    let mut game =
+        get_board(collection.games.remove(0)).map_err(|e| format!("{}: {}", file_name, e))?;
+    // animations show the main line by default
+    let path = match path {
+        Some(path) => path,
+        None if format == "gif" => {
+            let mut path = vec![];
+            let mut node = &game.node;
+            while let Some(child) = node.children.first() {
+                path.push(0);
+                node = child;
+            }
+            path
+        }
+        None => vec![],
+    };
+    if traverse(&game.node, &path).is_none() {
+        return Err(format!("there is no node {:?}", path));
+    }
+    game.path = path;
+    game.numbering = numbering;
+
+    let crop = match crop {
+        Some(crop) => Some(
+            parse_region(&crop, game.width, game.height)
+                .ok_or(format!("invalid region {}", crop))?,
+        ),
+        None => None,
+    };
+    let output = export_game(&game, crop, format, &style)?;
+    io::stdout()
+        .write_all(&output)
+        .map_err(|why| format!("couldn't write the diagram: {}", why))
+}
@@ -0,0 +1,516 @@
+// An owned game tree that can be edited and written back to SGF.
+//
+// The parser keeps the properties of a node in the order of the file and
+// remembers the text it was read from, including the whitespace between
+// nodes and game trees. Nodes that are not changed are written back
+// exactly as they were read; the accessors follow the ones of the sgf crate.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SgfError {
+    NoProperties,
+    EmptyProperty,
+    ParseError,
+}
+
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+pub struct Collection {
+    pub games: Vec<GameNode>,
+    // text after the last game tree
+    trailing: String,
+}
+
+#[derive(Clone)]
+pub struct GameNode {
+    properties: Vec<(String, Vec<String>)>,
+    pub children: Vec<GameNode>,
+
+    // the node as read from the file, dropped as soon as it is changed
+    raw: Option<String>,
+    // text before the ';' of the node
+    lead: String,
+    // text before the '(' and the ')' if the node starts a game tree
+    open: String,
+    close: String,
+}
+
+impl fmt::Debug for GameNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (id, values) in &self.properties {
+            write!(f, " {}", id)?;
+            for v in values {
+                write!(f, "[{}]", v)?;
+            }
+        }
+        write!(f, " }}")
+    }
+}
+
+impl GameNode {
+    pub fn new() -> GameNode {
+        GameNode {
+            properties: vec![],
+            children: vec![],
+            raw: None,
+            lead: String::new(),
+            open: String::new(),
+            close: String::new(),
+        }
+    }
+
+    fn get_property(&self, id: &str) -> Result<&Vec<String>, SgfError> {
+        self.properties
+            .iter()
+            .find(|p| p.0 == id)
+            .map(|p| &p.1)
+            .ok_or(SgfError::NoProperties)
+    }
+
+    fn first_value(&self, id: &str) -> Result<&str, SgfError> {
+        self.get_property(id)
+            .and_then(|v| v.first().map(|s| s.as_str()).ok_or(SgfError::EmptyProperty))
+    }
+
+    // replaces the values of a property, a new property is added at the end
+    pub fn set_property(&mut self, id: &str, values: Vec<String>) -> &mut Self {
+        self.raw = None;
+        if let Some(p) = self.properties.iter_mut().find(|p| p.0 == id) {
+            p.1 = values;
+            return self;
+        }
+        self.properties.push((id.to_string(), values));
+        self
+    }
+
+    pub fn remove_property(&mut self, id: &str) -> Option<Vec<String>> {
+        let i = self.properties.iter().position(|p| p.0 == id)?;
+        self.raw = None;
+        Some(self.properties.remove(i).1)
+    }
+
+    // drops the text read from the file, the node is written from its
+    // properties like a new one
+    pub fn normalize(&mut self) {
+        self.raw = None;
+    }
+
+    // a copy of the node without its children
+    pub fn without_children(&self) -> GameNode {
+        GameNode {
+            properties: self.properties.clone(),
+            children: vec![],
+            raw: self.raw.clone(),
+            lead: self.lead.clone(),
+            open: self.open.clone(),
+            close: self.close.clone(),
+        }
+    }
+
+    // exchanges the properties, and the text read for them, with those of
+    // another node, the children stay
+    pub fn swap_properties(&mut self, other: &mut GameNode) {
+        std::mem::swap(&mut self.properties, &mut other.properties);
+        std::mem::swap(&mut self.raw, &mut other.raw);
+    }
+
+    // the properties in the order of the file with their raw values
+    pub fn properties(&self) -> &[(String, Vec<String>)] {
+        &self.properties
+    }
+
+    pub fn property_ids(&self) -> Vec<&str> {
+        self.properties.iter().map(|p| p.0.as_str()).collect()
+    }
+
+    pub fn get_point(&self, id: &str) -> Result<String, SgfError> {
+        self.first_value(id).map(|s| s.to_string())
+    }
+
+    pub fn set_point(&mut self, id: &str, value: String) -> &mut Self {
+        self.set_property(id, vec![value])
+    }
+
+    pub fn get_points(&self, id: &str) -> Result<Vec<String>, SgfError> {
+        self.get_property(id).cloned()
+    }
+
+    pub fn get_number(&self, id: &str) -> Result<i32, SgfError> {
+        self.first_value(id)
+            .and_then(|s| s.trim().parse().map_err(|_| SgfError::ParseError))
+    }
+
+    pub fn get_real(&self, id: &str) -> Result<f32, SgfError> {
+        self.first_value(id)
+            .and_then(|s| s.trim().parse().map_err(|_| SgfError::ParseError))
+    }
+
+    pub fn get_number_number(&self, id: &str) -> Result<(i32, i32), SgfError> {
+        let value = self.first_value(id)?;
+        let mut compose = value.splitn(2, ':');
+        let first = compose.next().ok_or(SgfError::EmptyProperty)?;
+        let second = compose.next().ok_or(SgfError::EmptyProperty)?;
+        match (first.trim().parse(), second.trim().parse()) {
+            (Ok(a), Ok(b)) => Ok((a, b)),
+            _ => Err(SgfError::ParseError),
+        }
+    }
+
+    pub fn get_color(&self, id: &str) -> Result<char, SgfError> {
+        self.first_value(id)
+            .and_then(|s| s.chars().next().ok_or(SgfError::EmptyProperty))
+    }
+
+    pub fn get_double(&self, id: &str) -> Result<char, SgfError> {
+        self.first_value(id)
+            .and_then(|s| s.chars().next().ok_or(SgfError::EmptyProperty))
+    }
+
+    pub fn set_double(&mut self, id: &str, value: char) -> &mut Self {
+        self.set_property(id, vec![value.to_string()])
+    }
+
+    pub fn get_text(&self, id: &str) -> Result<String, SgfError> {
+        self.first_value(id).map(decode_text)
+    }
+
+    pub fn set_text(&mut self, id: &str, value: &str) -> &mut Self {
+        self.set_property(id, vec![encode_text(value)])
+    }
+
+    pub fn get_simple_text(&self, id: &str) -> Result<String, SgfError> {
+        self.first_value(id).map(decode_simple_text)
+    }
+
+    pub fn set_simple_text(&mut self, id: &str, value: &str) -> &mut Self {
+        self.set_property(id, vec![encode_text(&value.replace(['\n', '\r'], " "))])
+    }
+}
+
+// removes soft line breaks and escaping
+pub fn decode_text(s: &str) -> String {
+    let mut text = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(n) if n == '\n' || n == '\r' => {
+                // \r\n and \n\r are a single line break
+                match chars.peek() {
+                    Some(&m) if (m == '\n' || m == '\r') && m != n => {
+                        chars.next();
+                    }
+                    _ => {}
+                }
+            }
+            Some(n) => text.push(n),
+            None => {}
+        }
+    }
+    text
+}
+
+// like decode_text, but line breaks become spaces
+pub fn decode_simple_text(s: &str) -> String {
+    decode_text(s)
+        .replace("\r\n", " ")
+        .replace("\n\r", " ")
+        .replace(['\n', '\r'], " ")
+}
+
+pub fn encode_text(s: &str) -> String {
+    let mut text = String::new();
+    for c in s.chars() {
+        if c == ']' || c == '\\' || c == ':' {
+            text.push('\\');
+        }
+        text.push(c);
+    }
+    text
+}
+
+impl Collection {
+    pub fn from_sgf(text: &str) -> Result<Collection, SyntaxError> {
+        Parser { text, pos: 0 }.collection()
+    }
+}
+
+impl GameNode {
+    // the root node of the first game, the rest of the file is not parsed
+    pub fn root_from_sgf(text: &str) -> Result<GameNode, SyntaxError> {
+        let mut parser = Parser { text, pos: 0 };
+        match text.find('(') {
+            Some(i) => parser.pos = i + 1,
+            None => return Err(parser.error("no game tree found")),
+        }
+        let lead = parser.skip_whitespace();
+        if parser.peek() != Some(';') {
+            return Err(parser.error("expected ';'"));
+        }
+        parser.node(lead)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        &self.text[start..self.pos]
+    }
+
+    fn error(&self, message: &'static str) -> SyntaxError {
+        let before = &self.text[..self.pos];
+        SyntaxError {
+            line: before.matches('\n').count() + 1,
+            column: before.chars().rev().take_while(|c| *c != '\n').count() + 1,
+            message,
+        }
+    }
+
+    fn collection(&mut self) -> Result<Collection, SyntaxError> {
+        let mut games = vec![];
+        loop {
+            // anything before a game tree is ignored, but kept
+            let start = self.pos;
+            match self.text[self.pos..].find('(') {
+                Some(i) => self.pos += i,
+                None => break,
+            }
+            let open = &self.text[start..self.pos];
+            games.push(self.game_tree(open)?);
+        }
+        if games.is_empty() {
+            return Err(self.error("no game tree found"));
+        }
+        Ok(Collection {
+            games,
+            trailing: self.text[self.pos..].to_string(),
+        })
+    }
+
+    fn game_tree(&mut self, open: &str) -> Result<GameNode, SyntaxError> {
+        self.pos += 1; // '('
+        let mut sequence = vec![];
+        let mut whitespace = self.skip_whitespace();
+        while self.peek() == Some(';') {
+            sequence.push(self.node(whitespace)?);
+            whitespace = self.skip_whitespace();
+        }
+        if sequence.is_empty() {
+            return Err(self.error("expected ';'"));
+        }
+        let mut variations = vec![];
+        while self.peek() == Some('(') {
+            variations.push(self.game_tree(whitespace)?);
+            whitespace = self.skip_whitespace();
+        }
+        match self.peek() {
+            Some(')') => self.pos += 1,
+            Some(_) => return Err(self.error("expected ';', '(' or ')'")),
+            None => return Err(self.error("missing ')'")),
+        }
+
+        let mut node = sequence.pop().unwrap();
+        node.children = variations;
+        while let Some(mut parent) = sequence.pop() {
+            parent.children.push(node);
+            node = parent;
+        }
+        node.open = open.to_string();
+        node.close = whitespace.to_string();
+        Ok(node)
+    }
+
+    fn node(&mut self, lead: &str) -> Result<GameNode, SyntaxError> {
+        let start = self.pos;
+        self.pos += 1; // ';'
+        let mut properties = vec![];
+        loop {
+            let end = self.pos;
+            self.skip_whitespace();
+            if !self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                // the whitespace belongs to what follows
+                self.pos = end;
+                break;
+            }
+            let ident_start = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.pos += 1;
+            }
+            let ident = &self.text[ident_start..self.pos];
+            // FF[3] allows lower case letters in property names
+            let mut id: String = ident.chars().filter(|c| c.is_ascii_uppercase()).collect();
+            if id.is_empty() {
+                id = ident.to_string();
+            }
+
+            let mut values = vec![];
+            loop {
+                let end = self.pos;
+                self.skip_whitespace();
+                if self.peek() != Some('[') {
+                    self.pos = end;
+                    break;
+                }
+                values.push(self.value()?);
+            }
+            if values.is_empty() {
+                return Err(self.error("expected '['"));
+            }
+            properties.push((id, values));
+        }
+        Ok(GameNode {
+            properties,
+            children: vec![],
+            raw: Some(self.text[start..self.pos].to_string()),
+            lead: lead.to_string(),
+            open: String::new(),
+            close: String::new(),
+        })
+    }
+
+    fn value(&mut self) -> Result<String, SyntaxError> {
+        self.pos += 1; // '['
+        let start = self.pos;
+        let mut chars = self.text[start..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                ']' => {
+                    self.pos = start + i + 1;
+                    return Ok(self.text[start..start + i].to_string());
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("missing ']'"))
+    }
+}
+
+impl fmt::Display for Collection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for game in &self.games {
+            write_game_tree(f, game)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+fn write_game_tree(f: &mut fmt::Formatter, node: &GameNode) -> fmt::Result {
+    write!(f, "{}(", node.open)?;
+    let close = &node.close;
+    // a single child continues the sequence
+    let mut node = node;
+    loop {
+        write_node(f, node)?;
+        match node.children.len() {
+            0 => break,
+            1 => node = &node.children[0],
+            _ => {
+                for child in &node.children {
+                    write_game_tree(f, child)?;
+                }
+                break;
+            }
+        }
+    }
+    write!(f, "{})", close)
+}
+
+fn write_node(f: &mut fmt::Formatter, node: &GameNode) -> fmt::Result {
+    write!(f, "{}", node.lead)?;
+    if let Some(ref raw) = node.raw {
+        return write!(f, "{}", raw);
+    }
+    write!(f, ";")?;
+    for (id, values) in &node.properties {
+        write!(f, "{}", id)?;
+        for v in values {
+            write!(f, "[{}]", v)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        assert_eq!(decode_text("[test\\\ntest\\:\\]"), "[testtest:]");
+        assert_eq!(decode_simple_text("a\nb\r\nc"), "a b c");
+        assert_eq!(encode_text("]\\:"), "\\]\\\\\\:");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for sgf in &[
+            include_str!("../test_ff4_1.sgf"),
+            include_str!("../test_ff5.sgf"),
+            include_str!("../kigo_test2.sgf"),
+            "(;FF[4]C[a\\]b] ;B[aa]\n(;W[bb] ) ( ;W[cc]XY[private]))\n",
+        ] {
+            let c = Collection::from_sgf(sgf).unwrap();
+            assert_eq!(&c.to_string(), sgf);
+        }
+    }
+
+    #[test]
+    fn test_root() {
+        let root = GameNode::root_from_sgf("\n(\n;GM[1]PB[a]\n;B[aa](;W[bb]").unwrap();
+        assert_eq!(root.get_simple_text("PB").unwrap(), "a");
+        assert!(root.children.is_empty());
+        assert!(GameNode::root_from_sgf("(;GM[1]PB[a").is_err());
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut c = Collection::from_sgf("(;FF[4]GM[1]SZ[9]\n;B[ee]C[x] ;W[cc])").unwrap();
+        let root = &mut c.games[0];
+        assert_eq!(root.children[0].get_point("B").unwrap(), "ee");
+        root.children[0].set_point("B", "dd".to_string());
+        let mut child = GameNode::new();
+        child.set_point("W", "aa".to_string());
+        root.children[0].children.push(child);
+        assert_eq!(c.to_string(), "(;FF[4]GM[1]SZ[9]\n;B[dd]C[x]( ;W[cc])(;W[aa]))");
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let e = Collection::from_sgf("(;FF[4]\n;B[aa").err().unwrap();
+        assert_eq!((e.line, e.column), (2, 4));
+    }
+}
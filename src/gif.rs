@@ -0,0 +1,292 @@
+// Writing animated GIF files, all frames share one palette of 256 colors.
+
+use std::collections::HashMap;
+
+use raster::{Canvas, Color};
+
+// colors with 5 bits for each channel
+const BUCKETS: usize = 1 << 15;
+
+fn bucket(color: Color) -> usize {
+    (color[0] as usize >> 3) << 10 | (color[1] as usize >> 3) << 5 | color[2] as usize >> 3
+}
+
+pub struct Palette {
+    colors: Vec<Color>,
+    // the nearest palette color of each bucket
+    lookup: Vec<u8>,
+}
+
+impl Palette {
+    // the most common colors of the canvases
+    pub fn new(canvases: &[&Canvas]) -> Palette {
+        let mut counts = vec![0u64; BUCKETS];
+        let mut sums = vec![[0u64; 3]; BUCKETS];
+        for canvas in canvases {
+            for &pixel in &canvas.pixels {
+                let b = bucket(pixel);
+                counts[b] += 1;
+                for i in 0..3 {
+                    sums[b][i] += pixel[i] as u64;
+                }
+            }
+        }
+        let mut used: Vec<usize> = (0..BUCKETS).filter(|&b| counts[b] > 0).collect();
+        used.sort_by_key(|&b| std::cmp::Reverse(counts[b]));
+        used.truncate(256);
+        let colors: Vec<Color> = used
+            .iter()
+            .map(|&b| {
+                let mut color = [0; 3];
+                for i in 0..3 {
+                    color[i] = (sums[b][i] / counts[b]) as u8;
+                }
+                color
+            })
+            .collect();
+
+        let lookup = (0..BUCKETS)
+            .map(|b| {
+                // the middle of the bucket
+                let color = [
+                    ((b >> 10) << 3 | 4) as i32,
+                    ((b >> 5 & 31) << 3 | 4) as i32,
+                    ((b & 31) << 3 | 4) as i32,
+                ];
+                let distance =
+                    |c: &Color| (0..3).map(|i| (c[i] as i32 - color[i]).pow(2)).sum::<i32>();
+                (0..colors.len())
+                    .min_by_key(|&i| distance(&colors[i]))
+                    .unwrap_or(0) as u8
+            })
+            .collect();
+        Palette { colors, lookup }
+    }
+
+    fn index(&self, color: Color) -> u8 {
+        self.lookup[bucket(color)]
+    }
+}
+
+fn le16(n: usize) -> [u8; 2] {
+    [n as u8, (n >> 8) as u8]
+}
+
+pub struct Encoder {
+    out: Vec<u8>,
+    width: usize,
+    height: usize,
+    palette: Palette,
+    // the indexed pixels of the last frame, only the changes are written
+    previous: Option<Vec<u8>>,
+}
+
+impl Encoder {
+    pub fn new(width: usize, height: usize, palette: Palette) -> Encoder {
+        let mut out = b"GIF89a".to_vec();
+        out.extend(&le16(width));
+        out.extend(&le16(height));
+        // a global color table of 256 colors with 8 bits per channel
+        out.extend(&[0xf7, 0, 0]);
+        for i in 0..256 {
+            out.extend(palette.colors.get(i).unwrap_or(&[0, 0, 0]));
+        }
+        // repeat forever
+        out.extend(&[0x21, 0xff, 0x0b]);
+        out.extend(b"NETSCAPE2.0");
+        out.extend(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+        Encoder {
+            out,
+            width,
+            height,
+            palette,
+            previous: None,
+        }
+    }
+
+    // adds a frame shown for the delay in hundredths of a second
+    pub fn add_frame(&mut self, canvas: &Canvas, delay: usize) {
+        let pixels: Vec<u8> = canvas
+            .pixels
+            .iter()
+            .map(|&pixel| self.palette.index(pixel))
+            .collect();
+        let (width, height) = (self.width, self.height);
+
+        // the rectangle with the changed pixels
+        let (mut x0, mut y0, mut x1, mut y1) = (0, 0, width, height);
+        if let Some(ref previous) = self.previous {
+            let changed = |i: usize| pixels[i] != previous[i];
+            let rows: Vec<usize> = (0..height)
+                .filter(|&y| (0..width).any(|x| changed(y * width + x)))
+                .collect();
+            let columns: Vec<usize> = (0..width)
+                .filter(|&x| rows.iter().any(|&y| changed(y * width + x)))
+                .collect();
+            match (rows.first(), rows.last(), columns.first(), columns.last()) {
+                (Some(&top), Some(&bottom), Some(&left), Some(&right)) => {
+                    x0 = left;
+                    y0 = top;
+                    x1 = right + 1;
+                    y1 = bottom + 1;
+                }
+                // a frame needs at least one pixel
+                _ => {
+                    x1 = 1;
+                    y1 = 1;
+                }
+            }
+        }
+
+        // the graphic control extension, the frame stays when the next one is
+        // drawn
+        self.out.extend(&[0x21, 0xf9, 0x04, 0x04]);
+        self.out.extend(&le16(delay));
+        self.out.extend(&[0x00, 0x00]);
+
+        self.out.push(0x2c);
+        for &n in &[x0, y0, x1 - x0, y1 - y0] {
+            self.out.extend(&le16(n));
+        }
+        self.out.push(0x00);
+        let mut indices = Vec::with_capacity((x1 - x0) * (y1 - y0));
+        for y in y0..y1 {
+            indices.extend(&pixels[y * width + x0..y * width + x1]);
+        }
+        self.out.push(8);
+        for block in lzw(&indices).chunks(255) {
+            self.out.push(block.len() as u8);
+            self.out.extend(block);
+        }
+        self.out.push(0x00);
+        self.previous = Some(pixels);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.out.push(0x3b);
+        self.out
+    }
+}
+
+const CLEAR: u16 = 256;
+const END: u16 = 257;
+const MAX_CODES: u16 = 4096;
+
+// the LZW compression of GIF for 8 bit indices, there is at least one
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let (mut bits, mut count) = (0u32, 0u32);
+    let mut size = 9;
+    let mut write = |code: u16, size: u32| {
+        bits |= (code as u32) << count;
+        count += size;
+        while count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = END + 1;
+    write(CLEAR, size);
+    let mut prefix = indices[0] as u16;
+    for &index in &indices[1..] {
+        if let Some(&code) = codes.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        write(prefix, size);
+        if next < MAX_CODES {
+            // the decoder reads wider codes once the next code needs them
+            if next == 1 << size && size < 12 {
+                size += 1;
+            }
+            codes.insert((prefix, index), next);
+            next += 1;
+        } else {
+            write(CLEAR, size);
+            codes.clear();
+            next = END + 1;
+            size = 9;
+        }
+        prefix = index as u16;
+    }
+    write(prefix, size);
+    if next == 1 << size && size < 12 {
+        size += 1;
+    }
+    write(END, size);
+    if count > 0 {
+        out.push(bits as u8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let mut table: Vec<Vec<u8>> = vec![];
+        let (mut bits, mut count, mut size) = (0u32, 0, 9);
+        let mut bytes = data.iter();
+        let mut previous: Option<Vec<u8>> = None;
+        let mut out = vec![];
+        loop {
+            while count < size {
+                bits |= (*bytes.next().unwrap() as u32) << count;
+                count += 8;
+            }
+            let code = (bits & ((1 << size) - 1)) as usize;
+            bits >>= size;
+            count -= size;
+            if code == CLEAR as usize {
+                table = (0..258).map(|i| vec![i as u8]).collect();
+                size = 9;
+                previous = None;
+                continue;
+            }
+            if code == END as usize {
+                return out;
+            }
+            let entry = match previous {
+                Some(ref previous) => {
+                    let entry = if code < table.len() {
+                        table[code].clone()
+                    } else {
+                        let mut entry = previous.clone();
+                        entry.push(previous[0]);
+                        entry
+                    };
+                    if table.len() < MAX_CODES as usize {
+                        let mut new = previous.clone();
+                        new.push(entry[0]);
+                        table.push(new);
+                        if table.len() == 1 << size && size < 12 {
+                            size += 1;
+                        }
+                    }
+                    entry
+                }
+                None => table[code].clone(),
+            };
+            out.extend(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw() {
+        for indices in &[
+            vec![7],
+            vec![1, 1, 1, 1, 1, 1, 1],
+            // enough codes to fill the table
+            (0..20000u32)
+                .map(|i| (i * i % 251) as u8)
+                .collect::<Vec<_>>(),
+        ] {
+            assert_eq!(&unlzw(&lzw(indices)), indices);
+        }
+    }
+}
@@ -0,0 +1,529 @@
+// Reading the formats of the Go servers, Tygem (GIB), WBaduk (NGF) and
+// Pandanet (UGF), by writing the game as SGF. Names in these files are in
+// UTF-8 or often in EUC-KR or Shift_JIS, told apart by the bytes of the file.
+
+use std::path::Path;
+
+use encoding_rs::{EUC_KR, SHIFT_JIS};
+use game_tree::encode_text;
+use {load_collection, position_to_str};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gib,
+    Ngf,
+    Ugf,
+}
+
+// the format of a file that is not SGF, by its extension or its content
+pub fn detect(file_name: &str, bytes: &[u8]) -> Option<Format> {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("gib") => return Some(Format::Gib),
+        Some("ngf") => return Some(Format::Ngf),
+        Some("ugf") | Some("ugi") => return Some(Format::Ugf),
+        Some("sgf") => return None,
+        _ => {}
+    }
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+    let text = text.trim_start();
+    if text.starts_with("\\HS") || text.starts_with("\\GS") {
+        Some(Format::Gib)
+    } else if text.starts_with("[Header]") {
+        Some(Format::Ugf)
+    } else if !text.contains("(;")
+        && text
+            .lines()
+            .nth(1)
+            .is_some_and(|l| l.trim().parse::<usize>().is_ok())
+        && text.lines().any(|l| l.starts_with("PM"))
+    {
+        Some(Format::Ngf)
+    } else {
+        None
+    }
+}
+
+// the convert subcommand, the game is written as SGF to stdout
+pub fn convert_file(args: &[String]) -> Result<(), String> {
+    match args {
+        [file_name] => {
+            print!("{}", load_collection(file_name)?);
+            Ok(())
+        }
+        _ => Err("Usage: sgf-navigator convert file".to_string()),
+    }
+}
+
+// the name an imported file is saved under
+pub fn save_name(file_name: &str) -> String {
+    match detect(file_name, &[]) {
+        Some(_) => Path::new(file_name)
+            .with_extension("sgf")
+            .to_string_lossy()
+            .into_owned(),
+        None => file_name.to_string(),
+    }
+}
+
+pub fn to_sgf(format: Format, bytes: &[u8]) -> Result<String, String> {
+    let text = decode(bytes);
+    let record = match format {
+        Format::Gib => read_gib(&text),
+        Format::Ngf => read_ngf(&text),
+        Format::Ugf => read_ugf(&text),
+    }?;
+    Ok(record.to_sgf())
+}
+
+// the text in UTF-8, EUC-KR or Shift_JIS, the first one the bytes are valid
+// in, Korean files that are not valid are read as far as possible
+fn decode(bytes: &[u8]) -> String {
+    if let Ok(text) = ::std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    if is_euc_kr(bytes) {
+        return EUC_KR.decode_without_bom_handling(bytes).0.into_owned();
+    }
+    match SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes) {
+        Some(text) => text.into_owned(),
+        None => EUC_KR.decode_without_bom_handling(bytes).0.into_owned(),
+    }
+}
+
+// whether the characters beyond ASCII are all pairs of bytes from 0xa1 to
+// 0xfe, Shift_JIS uses smaller bytes for kana and most kanji
+fn is_euc_kr(bytes: &[u8]) -> bool {
+    let mut bytes = bytes.iter();
+    while let Some(&b) = bytes.next() {
+        if b >= 0x80 {
+            match bytes.next() {
+                Some(&next) if (0xa1..=0xfe).contains(&b) && (0xa1..=0xfe).contains(&next) => {}
+                _ => return false,
+            }
+        }
+    }
+    true
+}
+
+// a game with only a main line, as the servers record them
+struct Record {
+    size: usize,
+    properties: Vec<(&'static str, String)>,
+    setup: Vec<(usize, usize)>,
+    // 'B' or 'W' and the point, None for a pass
+    moves: Vec<(char, Option<(usize, usize)>)>,
+}
+
+impl Record {
+    fn new(size: usize) -> Record {
+        Record {
+            size,
+            properties: vec![],
+            setup: vec![],
+            moves: vec![],
+        }
+    }
+
+    fn set(&mut self, id: &'static str, value: &str) {
+        let value = value.trim();
+        if !value.is_empty() {
+            self.properties.push((id, value.to_string()));
+        }
+    }
+
+    // the name and rank from "name (3d)" or "name 3d"
+    fn set_player(&mut self, color: char, player: &str) {
+        let (name, rank) = split_rank(player);
+        let (name_id, rank_id) = if color == 'B' {
+            ("PB", "BR")
+        } else {
+            ("PW", "WR")
+        };
+        self.set(name_id, name);
+        if let Some(rank) = rank {
+            self.set(rank_id, &rank);
+        }
+    }
+
+    fn set_handicap(&mut self, stones: usize) {
+        if stones >= 2 {
+            self.set("HA", &stones.to_string());
+            self.setup = handicap_points(self.size, stones);
+        }
+    }
+
+    fn play(&mut self, color: char, x: usize, y: usize) {
+        let point = if x < self.size && y < self.size {
+            Some((x, y))
+        } else {
+            None
+        };
+        self.moves.push((color, point));
+    }
+
+    fn to_sgf(&self) -> String {
+        let mut sgf = format!("(;GM[1]FF[4]CA[UTF-8]SZ[{}]", self.size);
+        for (id, value) in &self.properties {
+            sgf.push_str(&format!("{}[{}]", id, encode_text(value)));
+        }
+        if !self.setup.is_empty() {
+            sgf.push_str("AB");
+            for &(x, y) in &self.setup {
+                sgf.push_str(&format!("[{}]", position_to_str(x, y)));
+            }
+        }
+        for (i, &(color, point)) in self.moves.iter().enumerate() {
+            sgf.push_str(if i % 10 == 0 { "\n;" } else { ";" });
+            match point {
+                Some((x, y)) => sgf.push_str(&format!("{}[{}]", color, position_to_str(x, y))),
+                None => sgf.push_str(&format!("{}[]", color)),
+            }
+        }
+        sgf.push_str(")\n");
+        sgf
+    }
+}
+
+fn split_rank(player: &str) -> (&str, Option<String>) {
+    let player = player.trim();
+    if player.ends_with(')') {
+        if let Some(open) = player.rfind('(') {
+            let rank = player[open + 1..player.len() - 1].trim();
+            return (player[..open].trim(), Some(rank.to_lowercase()));
+        }
+    }
+    match player.rsplit_once(' ') {
+        Some((name, rank))
+            if rank.starts_with(|c: char| c.is_ascii_digit())
+                && rank
+                    .trim_end_matches('*')
+                    .ends_with(['k', 'K', 'd', 'D', 'p', 'P']) =>
+        {
+            (name.trim(), Some(rank.trim_end_matches('*').to_lowercase()))
+        }
+        _ => (player, None),
+    }
+}
+
+// the usual places of the handicap stones
+fn handicap_points(size: usize, stones: usize) -> Vec<(usize, usize)> {
+    let edge = if size >= 13 { 3 } else { 2 };
+    let (low, middle, high) = (edge, size / 2, size - 1 - edge);
+    let mut points = vec![(high, low), (low, high), (high, high), (low, low)];
+    if stones >= 6 {
+        points.extend(&[(low, middle), (high, middle)]);
+    }
+    if stones >= 8 {
+        points.extend(&[(middle, low), (middle, high)]);
+    }
+    if stones % 2 == 1 && stones >= 5 {
+        points.push((middle, middle));
+    }
+    points.truncate(stones.min(9));
+    points
+}
+
+// a date like "2010- 3-20-17-54-22" or "20100320" as YYYY-MM-DD
+fn parse_date(s: &str) -> Option<String> {
+    let numbers: Vec<u32> = s
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .filter_map(|n| n.parse().ok())
+        .collect();
+    let (year, month, day) = match numbers.first() {
+        Some(&date) if date > 10_000_000 => (date / 10000, date / 100 % 100, date % 100),
+        Some(&year) if numbers.len() >= 3 => (year, numbers[1], numbers[2]),
+        _ => return None,
+    };
+    if (1..=12).contains(&month) && (1..=31).contains(&day) {
+        Some(format!("{:04}-{:02}-{:02}", year, month, day))
+    } else {
+        None
+    }
+}
+
+// Tygem files have a header of \[KEY=value\] lines and the moves as
+// "STO 0 <number> <color> <x> <y>" with 1 for black and 2 for white
+fn read_gib(text: &str) -> Result<Record, String> {
+    let mut record = Record::new(19);
+    let mut info = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        let header = line
+            .strip_prefix("\\[")
+            .and_then(|l| l.strip_suffix("\\]"))
+            .and_then(|l| l.split_once('='));
+        if let Some((key, value)) = header {
+            match key {
+                "GAMEBLACKNAME" => record.set_player('B', value),
+                "GAMEWHITENAME" => record.set_player('W', value),
+                "GAMENAME" => record.set("GN", value),
+                "GAMEPLACE" => record.set("PC", value),
+                "GAMEDATE" => {
+                    if let Some(date) = parse_date(value) {
+                        record.set("DT", &date);
+                    }
+                }
+                "GAMEINFOMAIN" => {
+                    info = value
+                        .split(',')
+                        .filter_map(|field| field.split_once(':'))
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect();
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["INI", _, _, handicap, ..] => {
+                record.set_handicap(handicap.parse().unwrap_or(0));
+            }
+            ["STO", _, _, color, x, y] => {
+                let color = if *color == "2" { 'W' } else { 'B' };
+                let x = x.parse().map_err(|_| format!("invalid move {}", line))?;
+                let y = y.parse().map_err(|_| format!("invalid move {}", line))?;
+                record.play(color, x, y);
+            }
+            ["SKI", ..] => {
+                let color = match record.moves.last() {
+                    Some(&('B', _)) => 'W',
+                    Some(_) => 'B',
+                    None if record.setup.is_empty() => 'B',
+                    None => 'W',
+                };
+                record.moves.push((color, None));
+            }
+            _ => {}
+        }
+    }
+
+    let value = |key: &str| info.iter().find(|f| f.0 == key).map(|f| f.1.trim());
+    if let Some(komi) = value("GONGJE").and_then(|k| k.parse::<f32>().ok()) {
+        record.set("KM", &(komi / 10.0).to_string());
+    }
+    let points = value("ZIPSU").and_then(|p| p.parse::<f32>().ok());
+    let result = match (value("GRLT"), points) {
+        (Some("0"), Some(points)) => Some(format!("B+{}", points / 10.0)),
+        (Some("1"), Some(points)) => Some(format!("W+{}", points / 10.0)),
+        (Some("3"), _) => Some("B+R".to_string()),
+        (Some("4"), _) => Some("W+R".to_string()),
+        (Some("7"), _) => Some("B+T".to_string()),
+        (Some("8"), _) => Some("W+T".to_string()),
+        _ => None,
+    };
+    if let Some(result) = result {
+        record.set("RE", &result);
+    }
+    if record.moves.is_empty() && record.setup.is_empty() {
+        return Err("there are no moves in the GIB file".to_string());
+    }
+    Ok(record)
+}
+
+// WBaduk files have twelve lines of game information and the moves as
+// "PM<number><color><x><y>..." where the coordinates count from 'B'
+fn read_ngf(text: &str) -> Result<Record, String> {
+    let lines: Vec<&str> = text.lines().map(|l| l.trim()).collect();
+    if lines.len() < 12 {
+        return Err("the NGF header is too short".to_string());
+    }
+    let size = lines[1]
+        .parse()
+        .map_err(|_| format!("invalid board size {}", lines[1]))?;
+    let mut record = Record::new(size);
+    record.set("GN", lines[0]);
+    record.set_player('W', lines[2]);
+    record.set_player('B', lines[3]);
+    record.set("PC", lines[4]);
+    record.set_handicap(lines[5].parse().unwrap_or(0));
+    if let Ok(komi) = lines[7].parse::<f32>() {
+        record.set("KM", &komi.to_string());
+    }
+    if let Some(date) = parse_date(lines[8]) {
+        record.set("DT", &date);
+    }
+    let result = lines[10].to_lowercase();
+    let winner = if result.starts_with("white") {
+        "W"
+    } else if result.starts_with("black") {
+        "B"
+    } else {
+        ""
+    };
+    if !winner.is_empty() {
+        let points = result
+            .split_whitespace()
+            .find_map(|word| word.parse::<f32>().ok());
+        let how = if result.contains("resign") {
+            "R".to_string()
+        } else if result.contains("time") {
+            "T".to_string()
+        } else {
+            points.map(|p| p.to_string()).unwrap_or_default()
+        };
+        record.set("RE", &format!("{}+{}", winner, how));
+    }
+
+    for line in &lines[12..] {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.len() < 7 || !line.starts_with("PM") {
+            continue;
+        }
+        let color = if chars[4] == 'W' { 'W' } else { 'B' };
+        let coordinate = |c: char| (c as usize).wrapping_sub('B' as usize);
+        record.play(color, coordinate(chars[5]), coordinate(chars[6]));
+    }
+    Ok(record)
+}
+
+// Pandanet files are sections of key=value lines, the moves are in [Data]
+// as "<x><y>,<color><number>,..." with the coordinates as in SGF in upper
+// case, stones with the number 0 are placed before the game
+fn read_ugf(text: &str) -> Result<Record, String> {
+    let mut section = "";
+    let mut header = vec![];
+    let mut data = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line;
+        } else if section == "[Header]" {
+            if let Some((key, value)) = line.split_once('=') {
+                header.push((key.trim(), value.trim()));
+            }
+        } else if section == "[Data]" && !line.is_empty() {
+            data.push(line);
+        }
+    }
+    let value = |key: &str| header.iter().find(|h| h.0 == key).map(|h| h.1);
+    let first = |key: &str| value(key).map(|v| v.split(',').next().unwrap_or("").trim());
+
+    let size = match first("Size") {
+        Some(size) => size
+            .parse()
+            .map_err(|_| format!("invalid board size {}", size))?,
+        None => 19,
+    };
+    let mut record = Record::new(size);
+    record.set("GN", first("Title").unwrap_or(""));
+    record.set("PC", first("Place").unwrap_or(""));
+    for &(key, color) in &[("PlayerB", 'B'), ("PlayerW", 'W')] {
+        if let Some(player) = value(key) {
+            let mut fields = player.split(',');
+            let name = fields.next().unwrap_or("");
+            match fields.next().map(|r| r.trim()).filter(|r| !r.is_empty()) {
+                Some(rank) => record.set_player(color, &format!("{} ({})", name, rank)),
+                None => record.set_player(color, name),
+            }
+        }
+    }
+    if let Some(date) = first("Date").and_then(parse_date) {
+        record.set("DT", &date);
+    }
+    if let Some(handicap) = value("Hdcp") {
+        let mut fields = handicap.split(',');
+        // the stones are in the data
+        match fields.next().and_then(|h| h.trim().parse::<usize>().ok()) {
+            Some(stones) if stones >= 2 => record.set("HA", &stones.to_string()),
+            _ => {}
+        }
+        if let Some(komi) = fields.next().and_then(|k| k.trim().parse::<f32>().ok()) {
+            record.set("KM", &komi.to_string());
+        }
+    }
+    if let Some(winner) = value("Winner") {
+        let mut fields = winner.split(',');
+        let color = fields.next().unwrap_or("").trim();
+        let points = fields.next().and_then(|p| p.trim().parse::<f32>().ok());
+        if color == "B" || color == "W" {
+            let how = match points {
+                Some(p) if p > 0.0 => p.to_string(),
+                _ => "R".to_string(),
+            };
+            record.set("RE", &format!("{}+{}", color, how));
+        }
+    }
+
+    for line in data {
+        let fields: Vec<&str> = line.split(',').collect();
+        let point: Vec<char> = fields[0].chars().collect();
+        let mut turn = fields.get(1).map(|t| t.trim()).unwrap_or("").chars();
+        let color = match turn.next() {
+            Some('W') => 'W',
+            Some('B') => 'B',
+            _ => continue,
+        };
+        if point.len() != 2 {
+            continue;
+        }
+        let x = (point[0] as usize).wrapping_sub('A' as usize);
+        let y = (point[1] as usize).wrapping_sub('A' as usize);
+        if turn.as_str() == "0" && color == 'B' && x < size && y < size {
+            record.setup.push((x, y));
+        } else {
+            record.play(color, x, y);
+        }
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let gib = "\\HS\n\\[GAMEBLACKNAME=kim (5D)\\]\n\\[GAMEWHITENAME=lee (7D)\\]\n\
+                   \\[GAMEDATE=2010- 3-20-17-54-22\\]\n\
+                   \\[GAMEINFOMAIN=GBKIND:3,GTIME:600,GONGJE:65,ZIPSU:35,GRLT:1\\]\n\\HE\n\
+                   \\GS\n2 1 0\nINI 0 1 0 &4\nSTO 0 2 1 15 3\nSTO 0 3 2 3 15\nSKI 0 4\n\\GE\n";
+        assert_eq!(detect("game.txt", gib.as_bytes()), Some(Format::Gib));
+        assert_eq!(
+            to_sgf(Format::Gib, gib.as_bytes()).unwrap(),
+            "(;GM[1]FF[4]CA[UTF-8]SZ[19]PB[kim]BR[5d]PW[lee]WR[7d]DT[2010-03-20]KM[6.5]\
+             RE[W+3.5]\n;B[pd];W[dp];B[])\n"
+        );
+
+        let ngf = "Friendly game\n19\nlee 3D*\nkim 2D\nWBaduk\n2\n0\n0.5\n\
+                   20100501 [14:25]\n5\nWhite wins by resignation\n2\n\
+                   PMABWEEEE\nPMACBQQQQ\n";
+        assert_eq!(detect("game", ngf.as_bytes()), Some(Format::Ngf));
+        assert_eq!(
+            to_sgf(Format::Ngf, ngf.as_bytes()).unwrap(),
+            "(;GM[1]FF[4]CA[UTF-8]SZ[19]GN[Friendly game]PW[lee]WR[3d]PB[kim]BR[2d]\
+             PC[WBaduk]HA[2]KM[0.5]DT[2010-05-01]RE[W+R]AB[pd][dp]\n;W[dd];B[pp])\n"
+        );
+
+        let ugf = "[Header]\nTitle=Open,\nSize=9\nHdcp=0,6.5\nPlayerB=sato,3d,,\n\
+                   PlayerW=ito,,,\nDate=2007/08/05,\nWinner=B,2.5\n[Data]\n\
+                   EE,B1,0,0\nCC,W2,0,0\n[Figure]\n";
+        assert_eq!(detect("game.ugi", ugf.as_bytes()), Some(Format::Ugf));
+        assert_eq!(
+            to_sgf(Format::Ugf, ugf.as_bytes()).unwrap(),
+            "(;GM[1]FF[4]CA[UTF-8]SZ[9]GN[Open]PB[sato]BR[3d]PW[ito]DT[2007-08-05]KM[6.5]\
+             RE[B+2.5]\n;B[ee];W[cc])\n"
+        );
+
+        // names in Korean and Japanese charsets
+        let gib = b"\\HS\n\\[GAMEBLACKNAME=\xc0\xcc\xc3\xa2\xc8\xa3 (9D)\\]\n\\HE\n\
+                    \\GS\n2 1 0\nINI 0 1 0 &4\nSTO 0 2 1 15 3\n\\GE\n";
+        assert!(to_sgf(Format::Gib, gib).unwrap().contains("PB[이창호]"));
+        let ugf = b"[Header]\nPlayerB=\x88\xe4\x8eR\x97T\x91\xbe,9d,,\n\
+                    PlayerW=\x82\xbf\x82\xe5\x82\xa4,,,\n[Data]\n";
+        let sgf = to_sgf(Format::Ugf, ugf).unwrap();
+        assert!(sgf.contains("PB[井山裕太]") && sgf.contains("PW[ちょう]"));
+        assert!(
+            to_sgf(Format::Ugf, "[Header]\nPlayerB=김,,,\n[Data]\n".as_bytes())
+                .unwrap()
+                .contains("PB[김]")
+        );
+
+        assert_eq!(save_name("games/a.gib"), "games/a.sgf");
+        assert_eq!(detect("a.sgf", b"(;GM[1])"), None);
+    }
+}
@@ -0,0 +1,429 @@
+// A database of the games in a directory for finding them by player, event,
+// date or result. It is a tab separated file, .sgf-index in the directory,
+// with a line of the game information in the root node of each game, every
+// game of a collection included. Games in zip archives are included, files
+// that did not change since the last index are not read again.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use browser::{browse_games, is_game_file, list_directory, table, Kind};
+use game_tree::{Collection, GameNode};
+use {archive, get_game_info, load_collection, navigate, read_file, read_text};
+
+const DATABASE: &str = ".sgf-index";
+const HEADER: &str = "# sgf-navigator index 2";
+// the root properties in the database, after the path, the index of the game
+// in the collection and the time the file was modified
+const FIELDS: [&str; 9] = ["PB", "BR", "PW", "WR", "EV", "DT", "RE", "KM", "HA"];
+// the search options and the properties they look at
+const OPTIONS: [(&str, &[&str]); 9] = [
+    ("--player", &["PB", "PW"]),
+    ("--black", &["PB"]),
+    ("--white", &["PW"]),
+    ("--rank", &["BR", "WR"]),
+    ("--event", &["EV"]),
+    ("--date", &["DT"]),
+    ("--result", &["RE"]),
+    ("--komi", &["KM"]),
+    ("--handicap", &["HA"]),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    // relative to the directory, games in archives as games.zip/game.sgf
+    path: String,
+    // the game of the collection, from 0
+    game: usize,
+    modified: u64,
+    values: Vec<String>,
+}
+
+impl Entry {
+    fn new(path: String, game: usize, modified: u64, root: &GameNode) -> Entry {
+        let info = get_game_info(root);
+        let values = vec![
+            info.black,
+            info.black_rank,
+            info.white,
+            info.white_rank,
+            info.event,
+            info.date,
+            info.result,
+            info.komi.map(|komi| komi.to_string()),
+            info.handicap.map(|handicap| handicap.to_string()),
+        ];
+        Entry {
+            path,
+            game,
+            modified,
+            values: values
+                .into_iter()
+                .map(|value| value.unwrap_or_default().replace(['\t', '\n', '\r'], " "))
+                .collect(),
+        }
+    }
+
+    fn value(&self, id: &str) -> &str {
+        &self.values[FIELDS.iter().position(|&field| field == id).unwrap()]
+    }
+
+    // the file the game is in, the archive for games in archives
+    fn file(&self) -> &str {
+        archive::split(&self.path).map_or(&self.path, |(archive_name, _)| archive_name)
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.path,
+            self.game,
+            self.modified,
+            self.values.join("\t")
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Entry> {
+        let mut columns = line.split('\t');
+        let path = columns.next()?.to_string();
+        let game = columns.next()?.parse().ok()?;
+        let modified = columns.next()?.parse().ok()?;
+        let values: Vec<String> = columns.map(|value| value.to_string()).collect();
+        if values.len() != FIELDS.len() {
+            return None;
+        }
+        Some(Entry {
+            path,
+            game,
+            modified,
+            values,
+        })
+    }
+
+    // the name, players, date and result as in the list of a directory, the
+    // games after the first of a collection with their number
+    fn columns(&self) -> [String; 5] {
+        let name = match self.game {
+            0 => self.path.clone(),
+            game => format!("{} #{}", self.path, game + 1),
+        };
+        let player = |name: &str, rank: &str| {
+            let name = if name.is_empty() { "?" } else { name };
+            format!("{} {}", name, rank).trim_end().to_string()
+        };
+        [
+            name,
+            player(self.value("PB"), self.value("BR")),
+            player(self.value("PW"), self.value("WR")),
+            self.value("DT").to_string(),
+            self.value("RE").to_string(),
+        ]
+    }
+}
+
+fn modified(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn read_database(dir: &Path) -> Result<Vec<Entry>, String> {
+    let file_name = dir.join(DATABASE);
+    let mut text = String::new();
+    File::open(&file_name)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|why| format!("couldn't read {}: {}", file_name.display(), why))?;
+    let mut lines = text.lines();
+    match lines.next() {
+        Some(HEADER) => {}
+        Some(header) if header.starts_with("# sgf-navigator index") => {
+            return Err(format!(
+                "{} is from another version, run index again",
+                file_name.display()
+            ))
+        }
+        _ => return Err(format!("{} is not an index", file_name.display())),
+    }
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            Entry::from_line(line).ok_or(format!(
+                "{}: line {} is corrupt",
+                file_name.display(),
+                i + 2
+            ))
+        })
+        .collect()
+}
+
+struct Indexer<'a> {
+    root: &'a Path,
+    // the entries of the last index by their file
+    previous: HashMap<String, Vec<Entry>>,
+    entries: Vec<Entry>,
+    read: usize,
+    failed: usize,
+}
+
+impl<'a> Indexer<'a> {
+    fn add(&mut self, entry: Entry) {
+        self.entries.push(entry);
+        if self.entries.len().is_multiple_of(1000) {
+            eprint!("\r{} games", self.entries.len());
+        }
+    }
+
+    fn add_games(&mut self, path: &str, modified: u64, collection: &Collection) {
+        for (game, root) in collection.games.iter().enumerate() {
+            self.add(Entry::new(path.to_string(), game, modified, root));
+        }
+    }
+
+    fn fail(&mut self, why: String) {
+        eprintln!("\r{}", why);
+        self.failed += 1;
+    }
+
+    fn walk(&mut self, dir: &Path) -> Result<(), String> {
+        for (kind, path) in list_directory(dir)? {
+            if kind == Kind::Directory {
+                self.walk(&path)?;
+                continue;
+            }
+            let relative = path
+                .strip_prefix(self.root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+            let modified = modified(&path);
+            match self.previous.remove(&relative) {
+                Some(ref entries) if entries.iter().all(|entry| entry.modified == modified) => {
+                    for entry in entries {
+                        self.add(entry.clone());
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            self.read += 1;
+            let name = path.to_string_lossy().into_owned();
+            if kind == Kind::Game {
+                match load_collection(&name) {
+                    Ok(collection) => self.add_games(&relative, modified, &collection),
+                    Err(why) => self.fail(why),
+                }
+                continue;
+            }
+            let data = match read_file(&name) {
+                Ok(data) => data,
+                Err(why) => {
+                    self.fail(why);
+                    continue;
+                }
+            };
+            let members = match archive::entries(&data) {
+                Ok(members) => members,
+                Err(why) => {
+                    self.fail(format!("{}: {}", name, why));
+                    continue;
+                }
+            };
+            for member in members {
+                if !is_game_file(&member.name) {
+                    continue;
+                }
+                let member_name = format!("{}/{}", name, member.name);
+                let collection = archive::extract(&data, &member)
+                    .and_then(|bytes| read_text(&member_name, bytes))
+                    .and_then(|text| {
+                        Collection::from_sgf(&text)
+                            .map_err(|e| format!("Error parsing {}: {}", member_name, e))
+                    });
+                match collection {
+                    Ok(collection) => {
+                        let path = format!("{}/{}", relative, member.name);
+                        self.add_games(&path, modified, &collection);
+                    }
+                    Err(why) => self.fail(why),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// the index subcommand, writes the database of the games in a directory
+pub fn index_directory(args: &[String]) -> Result<(), String> {
+    let dir = match args {
+        [dir] => Path::new(dir),
+        _ => return Err("Usage: sgf-navigator index directory".to_string()),
+    };
+    let mut previous: HashMap<String, Vec<Entry>> = HashMap::new();
+    // a missing or broken index is written from scratch
+    for entry in read_database(dir).unwrap_or_default() {
+        previous
+            .entry(entry.file().to_string())
+            .or_default()
+            .push(entry);
+    }
+    let mut indexer = Indexer {
+        root: dir,
+        previous,
+        entries: vec![],
+        read: 0,
+        failed: 0,
+    };
+    indexer.walk(dir)?;
+
+    let file_name = dir.join(DATABASE);
+    let mut text = format!("{}\n", HEADER);
+    for entry in &indexer.entries {
+        text.push_str(&entry.to_line());
+        text.push('\n');
+    }
+    File::create(&file_name)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|why| format!("couldn't write {}: {}", file_name.display(), why))?;
+    eprintln!(
+        "\r{} games in {}, {} files read, {} unreadable",
+        indexer.entries.len(),
+        file_name.display(),
+        indexer.read,
+        indexer.failed
+    );
+    Ok(())
+}
+
+// text matches anywhere and without regard to case, numbers match exactly
+fn matches(entry: &Entry, query: &[(&[&str], String)]) -> bool {
+    query.iter().all(|&(ids, ref wanted)| {
+        ids.iter().any(|&id| {
+            let value = entry.value(id);
+            match id {
+                "KM" | "HA" => {
+                    value.parse::<f32>().ok() == wanted.parse::<f32>().ok() && !value.is_empty()
+                }
+                _ => value.to_lowercase().contains(&wanted.to_lowercase()),
+            }
+        })
+    })
+}
+
+const USAGE: &str = "Usage: sgf-navigator search [directory] [--list] [--player name] \
+                     [--black name] [--white name] [--rank 5d] [--event name] [--date 2001] \
+                     [--result W+] [--komi 6.5] [--handicap 2]";
+
+// the search subcommand, the game found opens in the navigator, a list is
+// shown for more than one
+pub fn search(args: &[String]) -> Result<(), String> {
+    let mut dir = None;
+    let mut list = false;
+    let mut query: Vec<(&[&str], String)> = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--list" {
+            list = true;
+        } else if let Some(&(_, ids)) = OPTIONS.iter().find(|option| option.0 == arg) {
+            query.push((ids, args.next().ok_or(USAGE)?.clone()));
+        } else if dir.is_none() && !arg.starts_with("--") {
+            dir = Some(Path::new(arg));
+        } else {
+            return Err(USAGE.to_string());
+        }
+    }
+    let dir = dir.unwrap_or_else(|| Path::new("."));
+    let found: Vec<Entry> = read_database(dir)?
+        .into_iter()
+        .filter(|entry| matches(entry, &query))
+        .collect();
+    if found.is_empty() {
+        return Err("no game found".to_string());
+    }
+
+    if list {
+        let rows: Vec<[String; 5]> = found.iter().map(Entry::columns).collect();
+        let (heading, lines) = table(&rows);
+        println!("{}", heading);
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    let games: Vec<(String, usize, [String; 5])> = found
+        .iter()
+        .map(|entry| {
+            let file_name = dir.join(&entry.path).to_string_lossy().into_owned();
+            (file_name, entry.game, entry.columns())
+        })
+        .collect();
+    if let [(ref file_name, game, _)] = games[..] {
+        let collection = load_collection(file_name)?;
+        navigate(collection, file_name, game, &mut io::stdin());
+    } else {
+        let title = format!("{} games found", games.len());
+        browse_games(&title, &games, &mut io::stdin());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let root = GameNode::root_from_sgf(
+            "(;GM[1]PB[Troy\tSmith]BR[3d]PW[Ann]EV[Club\nLeague]RE[W+R]KM[6.5];B[aa])",
+        )
+        .unwrap();
+        let entry = Entry::new("games.zip/a.sgf".to_string(), 1, 12, &root);
+        let line = entry.to_line();
+        assert_eq!(
+            line,
+            "games.zip/a.sgf\t1\t12\tTroy Smith\t3d\tAnn\t\tClub League\t\tW+R\t6.5\t"
+        );
+        assert_eq!(Entry::from_line(&line), Some(entry.clone()));
+        assert_eq!(entry.file(), "games.zip");
+        assert_eq!(entry.columns()[0], "games.zip/a.sgf #2");
+
+        let query = |options: &[(&str, &str)]| {
+            let query: Vec<(&[&str], String)> = options
+                .iter()
+                .map(|&(name, value)| {
+                    let ids = OPTIONS.iter().find(|option| option.0 == name).unwrap().1;
+                    (ids, value.to_string())
+                })
+                .collect();
+            matches(&entry, &query)
+        };
+        assert!(query(&[("--player", "troy"), ("--result", "W+")]));
+        assert!(query(&[("--player", "ann"), ("--komi", "6.50")]));
+        assert!(!query(&[("--black", "ann")]));
+        assert!(!query(&[("--handicap", "0")]));
+    }
+
+    #[test]
+    fn test_collection() {
+        let mut indexer = Indexer {
+            root: Path::new("."),
+            previous: HashMap::new(),
+            entries: vec![],
+            read: 0,
+            failed: 0,
+        };
+        let collection = Collection::from_sgf("(;PB[Troy];B[aa])(;PB[Ann])").unwrap();
+        indexer.add_games("a.sgf", 12, &collection);
+        let games: Vec<(usize, &str)> = indexer
+            .entries
+            .iter()
+            .map(|entry| (entry.game, entry.value("PB")))
+            .collect();
+        assert_eq!(games, [(0, "Troy"), (1, "Ann")]);
+    }
+}
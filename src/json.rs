@@ -0,0 +1,206 @@
+// The json subcommand, the game tree with the properties of every node and
+// optionally the position after it, in the schema described in the README.
+
+use game_tree::{decode_text, GameNode};
+use Instruction::*;
+use {collect_moves, get_board, load_collection, GoColor, Position};
+
+// changes to the schema that break readers get a new version
+const VERSION: u32 = 1;
+
+const USAGE: &str = "Usage: sgf-navigator json [--positions] file";
+
+pub fn json_file(args: &[String]) -> Result<(), String> {
+    let (positions, file_name) = match args {
+        [file_name] => (false, file_name),
+        [option, file_name] if option == "--positions" => (true, file_name),
+        _ => return Err(USAGE.to_string()),
+    };
+    let collection = load_collection(file_name)?;
+    let mut games = vec![];
+    for (i, game) in collection.games.into_iter().enumerate() {
+        let game = get_board(game).map_err(|e| format!("game {}: {}", i + 1, e))?;
+        let mut nodes = vec![];
+        let position = Position::new(game.width, game.height);
+        write_nodes(&mut nodes, &game.node, &mut vec![], position, positions);
+        games.push(format!(
+            "{{\"size\": [{}, {}], \"nodes\": [\n{}\n]}}",
+            game.width,
+            game.height,
+            nodes.join(",\n")
+        ));
+    }
+    println!(
+        "{{\"version\": {}, \"file\": {}, \"games\": [\n{}\n]}}",
+        VERSION,
+        json_string(file_name),
+        games.join(",\n")
+    );
+    Ok(())
+}
+
+// the nodes of the tree in the order of the file, every node on a line
+fn write_nodes(
+    nodes: &mut Vec<String>,
+    node: &GameNode,
+    path: &mut Vec<usize>,
+    mut position: Position,
+    positions: bool,
+) {
+    let mut fields = vec![];
+    let indexes: Vec<String> = path.iter().map(|i| i.to_string()).collect();
+    fields.push(format!("\"path\": [{}]", indexes.join(", ")));
+    fields.push(format!("\"children\": {}", node.children.len()));
+
+    // the values of repeated properties are joined
+    let mut properties: Vec<(&str, Vec<String>)> = vec![];
+    for (id, values) in node.properties() {
+        let values = values.iter().map(|v| json_string(&decode_text(v)));
+        match properties.iter_mut().find(|p| p.0 == id) {
+            Some(p) => p.1.extend(values),
+            None => properties.push((id, values.collect())),
+        }
+    }
+    let properties: Vec<String> = properties
+        .iter()
+        .map(|(id, values)| format!("{}: [{}]", json_string(id), values.join(", ")))
+        .collect();
+    fields.push(format!("\"properties\": {{{}}}", properties.join(", ")));
+
+    let mut played = "null".to_string();
+    for instruction in collect_moves(node, &[]) {
+        position.apply(&instruction);
+        match instruction {
+            Move((x, y), color) if x < position.width && y < position.height => {
+                played = format!(
+                    "{{\"color\": \"{}\", \"point\": [{}, {}]}}",
+                    color_name(color),
+                    x,
+                    y
+                );
+            }
+            Move(_, color) | Pass(color) => {
+                played = format!("{{\"color\": \"{}\", \"point\": null}}", color_name(color));
+            }
+            _ => {}
+        }
+    }
+    fields.push(format!("\"move\": {}", played));
+
+    if positions {
+        let rows: Vec<String> = position
+            .board
+            .chunks(position.width)
+            .map(|row| {
+                let row: String = row
+                    .iter()
+                    .map(|&stone| match stone {
+                        1 => 'O',
+                        2 => 'X',
+                        _ => '.',
+                    })
+                    .collect();
+                json_string(&row)
+            })
+            .collect();
+        fields.push(format!(
+            "\"position\": {{\"board\": [{}], \"to_play\": \"{}\", \"move_number\": {}, \
+             \"captures\": {{\"B\": {}, \"W\": {}}}}}",
+            rows.join(", "),
+            color_name(position.next_player),
+            position.move_number,
+            position.black_prisoners,
+            position.white_prisoners
+        ));
+    }
+    nodes.push(format!("{{{}}}", fields.join(", ")));
+
+    for (i, child) in node.children.iter().enumerate() {
+        path.push(i);
+        write_nodes(nodes, child, path, position.clone(), positions);
+        path.pop();
+    }
+}
+
+fn color_name(color: GoColor) -> &'static str {
+    match color {
+        GoColor::Black => "B",
+        GoColor::White => "W",
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_tree::Collection;
+
+    #[test]
+    fn test_json() {
+        assert_eq!(json_string("a\"b\n"), "\"a\\\"b\\n\"");
+
+        let c = Collection::from_sgf("(;SZ[3]C[a\\]b](;B[bb];W[ab])(;B[]))").unwrap();
+        let mut nodes = vec![];
+        write_nodes(
+            &mut nodes,
+            &c.games[0],
+            &mut vec![],
+            Position::new(3, 3),
+            true,
+        );
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(
+            nodes[0],
+            "{\"path\": [], \"children\": 2, \"properties\": {\"SZ\": [\"3\"], \"C\": [\"a]b\"]}, \
+             \"move\": null, \"position\": {\"board\": [\"...\", \"...\", \"...\"], \
+             \"to_play\": \"B\", \"move_number\": 0, \"captures\": {\"B\": 0, \"W\": 0}}}"
+        );
+        assert!(nodes[2].starts_with("{\"path\": [0, 0], \"children\": 0"));
+        assert!(nodes[2].contains("\"move\": {\"color\": \"W\", \"point\": [0, 1]}"));
+        assert!(nodes[2].contains("\"board\": [\"...\", \"OX.\", \"...\"], \"to_play\": \"B\""));
+        assert!(nodes[3].contains("\"move\": {\"color\": \"B\", \"point\": null}"));
+    }
+
+    #[test]
+    fn test_json_file() {
+        let file_name = ::std::env::temp_dir().join(format!("json-{}.sgf", ::std::process::id()));
+        let file_name = file_name.to_string_lossy().into_owned();
+        let json = |sgf: &str| {
+            ::std::fs::write(&file_name, sgf).unwrap();
+            json_file(&["--positions".to_string(), file_name.clone()])
+        };
+        // Go on 19x19 without GM and SZ
+        assert_eq!(json("(;B[pd];W[dp])"), Ok(()));
+        assert_eq!(
+            json("(;GM[3]SZ[9])"),
+            Err("game 1: this is not a Go game".to_string())
+        );
+        assert_eq!(
+            json("(;SZ[9];B[e1])"),
+            Err("game 1: B: e1 is not a point".to_string())
+        );
+        assert_eq!(
+            json("(;SZ[9]AB[aa][jj])"),
+            Err("game 1: AB: jj is not on the board".to_string())
+        );
+        // markup isn't needed for the positions
+        assert_eq!(json("(;SZ[9]LB[jj:a]AR[aa:aa])"), Ok(()));
+        ::std::fs::remove_file(&file_name).unwrap();
+    }
+}
@@ -1,27 +1,45 @@
-#![feature(io)]
-extern crate petgraph;
-extern crate sgf;
+extern crate encoding_rs;
 extern crate termion;
 
+mod animation;
+mod archive;
+mod book;
+mod browser;
+mod check;
+mod clean;
+mod deflate;
+mod diagram;
+mod game_tree;
+mod gif;
+mod import;
+mod index;
+mod json;
+mod merge;
+mod png;
+mod raster;
+mod svg;
+mod text;
+
 use std::env;
+use std::fs;
+use std::process;
 use std::path::Path;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::error::Error;
-use sgf::sgf_node::SgfCollection;
-use sgf::sgf_node::SgfNode;
-use sgf::sgf_node::SgfError;
-
 use termion::clear;
 use termion::color;
+use termion::event::Key;
+use termion::input::TermRead;
 
-use petgraph::Graph;
-use petgraph::algo::tarjan_scc;
+use game_tree::Collection;
+use game_tree::GameNode;
+use game_tree::SgfError;
+use game_tree::decode_simple_text;
 
 use Instruction::*;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum GoColor {
     White,
     Black,
@@ -32,24 +50,70 @@ enum PointSt {
     Black,
     Free,
 }
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emphasis {
+    Normal, // the double value 1
+    Very,   // the double value 2
+}
+#[derive(Debug, Clone, Copy)]
+enum MoveAnnotation {
+    Bad(Emphasis),
+    Doubtful,
+    Interesting,
+    Tesuji(Emphasis),
+}
 #[derive(Debug)]
 enum Instruction {
     Move((usize, usize), GoColor),
     Setup((usize, usize), PointSt),
     NextPlayer(GoColor),
+    Pass(GoColor),
+    MoveNumber(i32),
+    Annotate(MoveAnnotation),
 }
 
-enum RuleSet {
-    AGA,      // rules of the American Go Association
-    GOE,      // the Ing rules of Goe
-    Japanese, // the Nihon-Kiin rule set
-    NZ,       // New Zealand rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Numbering {
+    Off,
+    All,
+    Last(usize),
+    SinceBranch,
 }
 
-struct Model<'a> {
-    node: &'a SgfNode,
-    path: std::vec::Vec<usize>,
+// what `p` puts on the board, a stone or one of the markup properties
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tool {
+    Stone,
+    BlackStones,
+    WhiteStones,
+    Letters,
+    Numbers,
+    Triangle,
+    Square,
+    Circle,
+    Cross,
+    Selected,
+    Dimmed,
+    Arrow,
+    Line,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+enum RuleSet {
+    AGA,           // rules of the American Go Association
+    GOE,           // the Ing rules of Goe
+    Japanese,      // the Nihon-Kiin rule set
+    NZ,            // New Zealand rules
+    Other(String), // anything else, kept as written in the file
+}
 
+struct GameInfo {
+    black: Option<String>,
+    black_rank: Option<String>,
+    black_team: Option<String>,
+    white: Option<String>,
+    white_rank: Option<String>,
+    white_team: Option<String>,
     annotation: Option<String>,
     copyright: Option<String>,
     date: Option<String>,
@@ -67,35 +131,164 @@ struct Model<'a> {
     source: Option<String>,
     time_limits: Option<f32>,
     user: Option<String>,
+}
+
+struct Model {
+    node: GameNode,
+    path: std::vec::Vec<usize>,
+    cursor: (usize, usize),
+    // the number of edits to undo when the game was saved, None when undo
+    // and redo can't go back there
+    saved: Option<usize>,
+
+    application: Option<String>,
+    info: GameInfo,
+    numbering: Numbering,
+    tool: Tool,
+    // the first point of an arrow or line
+    tool_start: Option<(usize, usize)>,
+    // a copied subtree
+    clipboard: Option<GameNode>,
+    // the edits to undo, the last one first, and the undone ones to redo
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
 
     width: usize,
     height: usize,
 }
 
-fn get_board<'a>(node: &'a SgfNode) -> Result<Model<'a>, SgfError> {
-    let char_set = node.get_text("CA");
-    let file_format = node.get_number("FF");
-    let game_type = node.get_number("GM").unwrap();
-    if game_type != 1 {
-        panic!("Error: this is not a Go game!");
+// an edit to undo or redo, the change that reverts it and the path before it
+struct Edit {
+    description: String,
+    change: Change,
+    path: Vec<usize>,
+}
+
+// a change of the tree, applying it returns the change that reverts it
+enum Change {
+    // the properties of the node at the path, swapped with those of a node
+    // without children
+    Properties(Vec<usize>, GameNode),
+    // a subtree that becomes the child at the path
+    Insert(Vec<usize>, GameNode),
+    // removes the child at the path
+    Remove(Vec<usize>),
+    // moves the child at the path to another index among its siblings
+    Move(Vec<usize>, usize),
+}
+
+const GAME_INFO_PROPERTIES: [&str; 23] = [
+    "AN", "BR", "BT", "CP", "DT", "EV", "GN", "GC", "HA", "KM", "ON", "OT", "PB", "PC", "PW",
+    "RE", "RO", "RU", "SO", "TM", "US", "WR", "WT",
+];
+// the other kinds of properties of FF[4] that the subcommands look at
+const ROOT_PROPERTIES: [&str; 6] = ["AP", "CA", "FF", "GM", "ST", "SZ"];
+const MOVE_PROPERTIES: [&str; 4] = ["B", "W", "KO", "MN"];
+const SETUP_PROPERTIES: [&str; 4] = ["AB", "AE", "AW", "PL"];
+// only one of each group is allowed in a node
+const MOVE_ANNOTATIONS: [&str; 4] = ["BM", "DO", "IT", "TE"];
+const POSITION_ANNOTATIONS: [&str; 4] = ["GB", "GW", "DM", "UC"];
+// the properties with lists of points, which may be compressed like "aa:cc"
+const POINT_LISTS: [&str; 12] = [
+    "AB", "AE", "AW", "CR", "DD", "MA", "SL", "SQ", "TB", "TR", "TW", "VW",
+];
+
+// the width and height of the board from SZ, like SZ[19] or SZ[19:13], 19x19
+// when it is missing
+fn board_size(root: &GameNode) -> Result<(usize, usize), String> {
+    let size = match root.get_number("SZ") {
+        Ok(w) => Ok((w, w)),
+        Err(SgfError::NoProperties) => Ok((19, 19)),
+        Err(_) => root.get_number_number("SZ"),
+    };
+    match size {
+        Ok((w, h)) if w > 0 && h > 0 && w <= 52 && h <= 52 => Ok((w as usize, h as usize)),
+        _ => Err("invalid board size".to_string()),
+    }
+}
+
+// the game at the root node for the navigator, a missing GM or SZ is Go on
+// a 19x19 board, moves and setup stones that are malformed or off the board
+// are errors, markup like that is left out when it is shown
+fn get_board(node: GameNode) -> Result<Model, String> {
+    match node.get_number("GM") {
+        Ok(1) | Err(SgfError::NoProperties) => {}
+        _ => return Err("this is not a Go game".to_string()),
     }
 
     //    let style = node.get_number("ST").unwrap();
-    let (width, height) = match node.get_number("SZ") {
-        Err(m) => {
-            println!("no quadratic field");
-            let (w, h) = node.get_number_number("SZ")
-                .expect("Error no field size defined!");
-            (w as usize, h as usize)
-        }
-        Ok(w) => (w as usize, w as usize),
-    };
+    let (width, height) = board_size(&node)?;
+    let mut nodes = vec![&node];
+    while let Some(n) = nodes.pop() {
+        for id in &["B", "W", "AB", "AW", "AE"] {
+            check::check_points(n, id, width, height).map_err(|why| format!("{}: {}", id, why))?;
+        }
+        nodes.extend(n.children.iter());
+    }
 
     // Root properties
     Ok(Model {
-        node: node,
         path: vec![],
+        cursor: (0, 0),
+        saved: Some(0),
+
+        application: node.get_simple_text("AP").ok(),
+        info: get_game_info(game_info_node(&node, &[])),
+        numbering: Numbering::Off,
+        tool: Tool::Stone,
+        tool_start: None,
+        clipboard: None,
+        undo: vec![],
+        redo: vec![],
+
+        node,
+        width,
+        height,
+    })
+}
+
+// the first game of an SGF text for the tests
+#[cfg(test)]
+fn model(sgf: &str) -> Model {
+    get_board(Collection::from_sgf(sgf).unwrap().games.remove(0)).unwrap()
+}
+
+fn has_game_info(node: &GameNode) -> bool {
+    GAME_INFO_PROPERTIES
+        .iter()
+        .any(|id| node.get_points(id).is_ok())
+}
+
+// Game-info properties belong to the first node of a game that has them.
+// In a collection merged from several games this is not the root but the
+// node where the games branch off, so look along the current path first.
+// If we have not reached it yet, look ahead along the main line.
+fn game_info_node<'a>(node: &'a GameNode, path: &[usize]) -> &'a GameNode {
+    let mut cur = node;
+    for i in path {
+        if has_game_info(cur) {
+            return cur;
+        }
+        cur = &cur.children[*i];
+    }
+    while !has_game_info(cur) {
+        if let Some(child) = cur.children.first() {
+            cur = child;
+        } else {
+            return node;
+        }
+    }
+    cur
+}
 
+fn get_game_info(node: &GameNode) -> GameInfo {
+    GameInfo {
+        black: node.get_simple_text("PB").ok(),
+        black_rank: node.get_simple_text("BR").ok(),
+        black_team: node.get_simple_text("BT").ok(),
+        white: node.get_simple_text("PW").ok(),
+        white_rank: node.get_simple_text("WR").ok(),
+        white_team: node.get_simple_text("WT").ok(),
         annotation: node.get_text("AN").ok(),
         copyright: node.get_text("CP").ok(),
         date: node.get_text("DT").ok(),
@@ -118,7 +311,7 @@ fn get_board<'a>(node: &'a SgfNode) -> Result<Model<'a>, SgfError> {
                 } else if s == "NZ" {
                     Some(RuleSet::NZ)
                 } else {
-                    None
+                    Some(RuleSet::Other(s))
                 }
             }
             _ => None,
@@ -128,348 +321,711 @@ fn get_board<'a>(node: &'a SgfNode) -> Result<Model<'a>, SgfError> {
         source: node.get_text("SO").ok(),
         time_limits: node.get_real("TM").ok(),
         user: node.get_text("US").ok(),
+    }
+}
 
-        width: width,
-        height: height,
-    })
+fn update_game_info(game: &mut Model) {
+    game.info = get_game_info(game_info_node(&game.node, &game.path));
 }
 
 fn show_details(game: &Model) {
-    if let Ok(ref name) = game.node.get_text("AP") {
-        println!("Application: {}", name);
-    } else {
-        println!("Application:");
+    let info = &game.info;
+    println!("{}", clear::All);
+    println!("Game information");
+    println!();
+
+    if let Some(ref name) = info.game_name {
+        println!("Game:        {}", name);
+    }
+    show_player("Black:      ", &info.black, &info.black_rank, &info.black_team);
+    show_player("White:      ", &info.white, &info.white_rank, &info.white_team);
+    if let Some(ref result) = info.result {
+        println!("Result:      {}", format_result(result));
+    }
+    if let Some(ref date) = info.date {
+        println!("Date:        {}", format_date(date));
+    }
+    if let Some(ref event) = info.event {
+        println!("Event:       {}", event);
+    }
+    if let Some(ref round) = info.round {
+        println!("Round:       {}", round);
+    }
+    if let Some(ref place) = info.place {
+        println!("Place:       {}", place);
+    }
+    if let Some(handicap) = info.handicap {
+        println!("Handicap:    {}", handicap);
+    }
+    if let Some(komi) = info.komi {
+        println!("Komi:        {}", komi);
+    }
+    if let Some(ref rules) = info.rules {
+        println!("Rules:       {}", rules_name(rules));
+    }
+    if let Some(time) = info.time_limits {
+        println!("Time:        {}", format_time(time));
+    }
+    if let Some(ref over_time) = info.over_time {
+        println!("Overtime:    {}", format_over_time(over_time));
+    }
+    if let Some(ref opening) = info.opening {
+        println!("Opening:     {}", opening);
+    }
+    if let Some(ref annotation) = info.annotation {
+        println!("Annotation:  {}", annotation);
+    }
+    if let Some(ref source) = info.source {
+        println!("Source:      {}", source);
+    }
+    if let Some(ref user) = info.user {
+        println!("User:        {}", user);
+    }
+    if let Some(ref copyright) = info.copyright {
+        println!("Copyright:   {}", copyright);
+    }
+    if let Some(ref application) = game.application {
+        println!("Application: {}", application.replacen(":", " ", 1));
+    }
+    if let Some(ref info) = info.game_info {
+        println!();
+        println!("{}", info);
+    }
+    println!();
+    println!("press i to return to the board");
+}
+
+fn show_player(
+    label: &str,
+    name: &Option<String>,
+    rank: &Option<String>,
+    team: &Option<String>,
+) {
+    print!("{} {}", label, name.as_ref().map_or("Unknown", |n| n.as_str()));
+    if let Some(ref rank) = *rank {
+        print!(" ({})", rank);
+    }
+    if let Some(ref team) = *team {
+        print!(", Team: {}", team);
+    }
+    println!();
+}
+
+fn rules_name(rules: &RuleSet) -> &str {
+    match *rules {
+        RuleSet::AGA => "AGA (American Go Association)",
+        RuleSet::GOE => "GOE (Ing rules)",
+        RuleSet::Japanese => "Japanese (Nihon-Kiin)",
+        RuleSet::NZ => "New Zealand",
+        RuleSet::Other(ref s) => s,
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// DT[] is a comma separated list of YYYY-MM-DD, YYYY-MM or YYYY dates.
+// Later entries may be shortened to MM-DD or DD, e.g. "1996-05-06,07,08"
+// or "1996-12-27,28,1997-01-03", in which case the missing parts are taken
+// from the previous date.
+fn format_date(dt: &str) -> String {
+    let mut dates = vec![];
+    let mut year = None;
+    let mut month = None;
+    for entry in dt.split(',') {
+        let parts: Vec<&str> = entry.trim().split('-').collect();
+        let numbers: Vec<u32> = parts.iter().filter_map(|p| p.parse().ok()).collect();
+        if numbers.len() != parts.len() {
+            // not in FF[4] format, show it as it is
+            return dt.to_string();
+        }
+        let date = match (parts.len(), parts[0].len()) {
+            (3, _) => (Some(numbers[0]), Some(numbers[1]), Some(numbers[2])),
+            (2, 4) => (Some(numbers[0]), Some(numbers[1]), None),
+            (2, _) => (year, Some(numbers[0]), Some(numbers[1])),
+            (1, 4) => (Some(numbers[0]), None, None),
+            (1, _) if month.is_some() => (year, month, Some(numbers[0])),
+            _ => return dt.to_string(),
+        };
+        year = date.0;
+        month = date.1;
+        dates.push(date);
+    }
+
+    let mut formatted = vec![];
+    for date in dates {
+        let month_name = date.1.and_then(|m| MONTHS.get((m as usize).wrapping_sub(1)));
+        formatted.push(match (date.0, month_name, date.2) {
+            (Some(y), Some(m), Some(d)) => format!("{} {} {}", d, m, y),
+            (Some(y), Some(m), None) => format!("{} {}", m, y),
+            (Some(y), None, None) => format!("{}", y),
+            _ => return dt.to_string(),
+        });
+    }
+    formatted.join(", ")
+}
+
+// TM[] is given in seconds
+fn format_time(seconds: f32) -> String {
+    let total = seconds.round() as u32;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    let mut parts = vec![];
+    if h > 0 {
+        parts.push(format!("{}h", h));
+    }
+    if m > 0 {
+        parts.push(format!("{}min", m));
+    }
+    if s > 0 || parts.is_empty() {
+        parts.push(format!("{}s", s));
+    }
+    format!("{} per player", parts.join(" "))
+}
+
+// OT[] is free text, but most applications write one of
+// "5x30 byo-yomi" or "25/600 Canadian".
+fn format_over_time(ot: &str) -> String {
+    let mut words = ot.split_whitespace();
+    let spec = words.next().unwrap_or("");
+    let kind = words.next().unwrap_or("").to_lowercase();
+    if let Some(i) = spec.find('x') {
+        if let (Ok(periods), Ok(seconds)) = (spec[..i].parse::<u32>(), spec[i + 1..].parse::<f32>()) {
+            if kind.is_empty() || kind.starts_with("byo") {
+                return format!(
+                    "byo-yomi, {} periods of {}",
+                    periods,
+                    format_time(seconds).trim_end_matches(" per player")
+                );
+            }
+        }
+    }
+    if let Some(i) = spec.find('/') {
+        if let (Ok(stones), Ok(seconds)) = (spec[..i].parse::<u32>(), spec[i + 1..].parse::<f32>()) {
+            if kind.is_empty() || kind.starts_with("canad") {
+                return format!(
+                    "Canadian, {} moves in {}",
+                    stones,
+                    format_time(seconds).trim_end_matches(" per player")
+                );
+            }
+        }
+    }
+    ot.to_string()
+}
+
+fn format_result(re: &str) -> String {
+    let (winner, margin) = match re.split_at(re.find('+').unwrap_or(re.len())) {
+        // RE[B] and RE[W] have no margin
+        ("B", m) => ("Black", m.trim_start_matches('+')),
+        ("W", m) => ("White", m.trim_start_matches('+')),
+        ("0", _) | ("Draw", _) => return "Draw".to_string(),
+        ("Void", _) => return "No result".to_string(),
+        ("?", _) => return "Unknown".to_string(),
+        _ => return re.to_string(),
+    };
+    match margin {
+        "" => format!("{} wins", winner),
+        "R" | "Resign" => format!("{} wins by resignation", winner),
+        "T" | "Time" => format!("{} wins on time", winner),
+        "F" | "Forfeit" => format!("{} wins by forfeit", winner),
+        points => format!("{} wins by {} points", winner, points),
     }
 }
 
 fn show_board(game: &Model) {
     println!("{}", clear::All);
 
-    // show some root properties
-    if let Ok(ref name) = game.node.get_text("PW") {
+    let width = game.width;
+    let height = game.height;
+
+    let cur_node = traverse(&game.node, &game.path).expect("Error invalid path");
+
+    // collect instructions
+    let instructions = collect_moves(&game.node, &game.path);
+    let last = last_move(&instructions);
+    let numbered_from = first_numbered_move(game, &instructions);
+    let (position, numbers, footnotes) = replay(game, &instructions);
+    let board = &position.board;
+
+    // show the players from the game-info node
+    let info = &game.info;
+    if let Some(ref name) = info.white {
         print!("White: {}, ", name);
     } else {
         print!("White: Unknown, ");
     }
-    if let Ok(ref name) = game.node.get_text("WT") {
+    if let Some(ref name) = info.white_team {
         print!("Team: {}, ", name);
     }
-    if let Ok(ref rank) = game.node.get_text("WR") {
-        println!("Rank: {}", rank);
+    if let Some(ref rank) = info.white_rank {
+        print!("Rank: {}, ", rank);
     } else {
-        println!("Rank: ? ");
+        print!("Rank: ?, ");
     }
-    if let Ok(ref name) = game.node.get_text("PB") {
+    println!("Prisoners: {}", position.white_prisoners);
+    if let Some(ref name) = info.black {
         print!("Black: {}, ", name);
     } else {
         print!("Black: Unknown, ");
     }
-    if let Ok(ref name) = game.node.get_text("BT") {
+    if let Some(ref name) = info.black_team {
         print!("Team: {}, ", name);
     }
-    if let Ok(ref rank) = game.node.get_text("BR") {
-        println!("Rank: {}", rank);
+    if let Some(ref rank) = info.black_rank {
+        print!("Rank: {}, ", rank);
     } else {
-        println!("Rank: ? ");
+        print!("Rank: ?, ");
     }
+    println!("Prisoners: {}", position.black_prisoners);
 
-    let width = game.width;
-    let height = game.height;
-
-    println!("path: {:?}", game.path);
+    print!("path: {:?}", game.path);
+    print!(", cursor: {}", format_point(game.cursor.0, game.cursor.1, height));
+    if let Some(handicap) = info.handicap {
+        print!(", Handicap: {}", handicap);
+    }
+    if let Some(komi) = info.komi {
+        print!(", Komi: {}", komi);
+    }
+    match game.numbering {
+        Numbering::Off => {}
+        Numbering::All => print!(", numbers: all"),
+        Numbering::Last(n) => print!(", numbers: last {}", n),
+        Numbering::SinceBranch => print!(", numbers: since branch"),
+    }
+    if game.tool != Tool::Stone {
+        print!(", tool: {}", tool_name(game.tool));
+    }
+    if let Some((x, y)) = game.tool_start {
+        print!(" from {}", format_point(x, y, height));
+    }
+    println!();
     let mut previous = game.path.clone();
-    if previous.len() > 0 {
+    if !previous.is_empty() {
         previous.pop();
-        if let Some(prev_node) = traverse(game.node, &previous) {
+        if let Some(prev_node) = traverse(&game.node, &previous) {
             println!("alternatives: {:?}", prev_node.children);
         } else {
             panic!("Error invalid path.");
         }
     }
 
-    if let Some(cur_node) = traverse(game.node, &game.path) {
-        let mut board = vec![0; width * height];
+    for y in 0..(height) {
+        for x in 0..width {
+            let pos = y * width + x;
+            let symbol = match (board[pos], numbers[pos]) {
+                (0, _) => "+".to_string(),
+                (_, Some(n)) => n.to_string(),
+                _ => "●".to_string(),
+            };
+            // numbers need wider columns
+            let symbol = if numbered_from.is_some() {
+                format!("{:>3}", symbol)
+            } else {
+                symbol
+            };
+            let symbol = if game.cursor == (x, y) {
+                format!(
+                    "{}{}{}",
+                    color::Bg(color::LightBlack),
+                    symbol,
+                    color::Bg(color::Reset)
+                )
+            } else {
+                symbol
+            };
+            match board[pos] {
+                0 => print!("{}", symbol),
+                1 => print!(
+                    "{red}{}{reset}",
+                    symbol,
+                    red = color::Fg(color::Red),
+                    reset = color::Fg(color::Reset)
+                ),
+                2 => print!(
+                    "{blue}{}{reset}",
+                    symbol,
+                    blue = color::Fg(color::Blue),
+                    reset = color::Fg(color::Reset)
+                ),
+                _ => println!(
+                    "{red}Unknown player{reset}",
+                    red = color::Fg(color::Red),
+                    reset = color::Fg(color::Reset)
+                ),
+            }
+        }
+        println!();
+    }
+    if !footnotes.is_empty() {
+        println!("{}", footnotes.join(", "));
+    }
+
+    // Show current node annotations
+    if let Ok(node_name) = cur_node.get_simple_text("N") {
+        println!("Node name: {}", node_name);
+    }
+    if let Ok(comment) = cur_node.get_text("C") {
+        println!("Comment: {}", comment);
+    }
+    show_badges(cur_node, last, height);
+
+    // Markup properties
+    if let Ok(labels) = cur_node.get_points("LB") {
+        println!("labels: {:?}", labels);
+    }
+    if let Ok(points) = cur_node.get_points("MA") {
+        println!("mark x: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("CR") {
+        println!("circles: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("SQ") {
+        println!("squares: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("TR") {
+        println!("triangles: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("SL") {
+        println!("selected: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("DD") {
+        println!("DD dim: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("AR") {
+        println!("arrows: {:?}", points);
+    }
+    if let Ok(points) = cur_node.get_points("LN") {
+        println!("lines: {:?}", points);
+    }
+}
+
+fn get_emphasis(node: &GameNode, id: &str) -> Option<Emphasis> {
+    match node.get_double(id) {
+        Ok('2') => Some(Emphasis::Very),
+        Ok(_) | Err(SgfError::EmptyProperty) => Some(Emphasis::Normal),
+        Err(_) => None,
+    }
+}
 
-        let mut white_connections = Graph::<usize, usize>::new();
-        let mut black_connections = Graph::<usize, usize>::new();
+// the last move played and the annotations collected for it
+fn last_move(
+    instructions: &[Instruction],
+) -> Option<((usize, usize), GoColor, Vec<MoveAnnotation>)> {
+    let mut annotations = vec![];
+    for instr in instructions.iter().rev() {
+        match *instr {
+            Annotate(a) => annotations.push(a),
+            Move(pos, color) => return Some((pos, color, annotations)),
+            _ => {}
+        }
+    }
+    None
+}
 
-        // collect instructions
-        let instructions = collect_moves(game.node, &game.path);
+fn move_badge(annotation: &MoveAnnotation) -> &'static str {
+    match *annotation {
+        MoveAnnotation::Tesuji(Emphasis::Normal) => "!",
+        MoveAnnotation::Tesuji(Emphasis::Very) => "!!",
+        MoveAnnotation::Bad(Emphasis::Normal) => "?",
+        MoveAnnotation::Bad(Emphasis::Very) => "??",
+        MoveAnnotation::Interesting => "!?",
+        MoveAnnotation::Doubtful => "?!",
+    }
+}
 
-        // TODO: some state for the display
-        let mut next_player = GoColor::White;
-        for instr in instructions {
-            match instr {
-                Move((x, y), GoColor::White) => {
-                    board[y * width + x] = 1;
-                    let mut neighbors = vec![];
-                    if x > 0 {
-                        neighbors.push(y * width + x.checked_sub(1).unwrap());
-                        if y > 0 {
-                            neighbors.push(
-                                y.checked_sub(1).unwrap() * width + x.checked_sub(1).unwrap(),
-                            );
-                        }
-                    }
-                    if y > 0 {
-                        neighbors.push(y.checked_sub(1).unwrap() * width + x);
-                    }
-                    if x + 1 < width {
-                        neighbors.push(y * width + x + 1);
-                        if y + 1 < height {
-                            neighbors.push(y + 1 * width + x + 1);
-                        }
-                    }
-                    if y + 1 < height {
-                        neighbors.push(y + 1 * width + x);
-                    }
+fn position_badges(node: &GameNode) -> Vec<&'static str> {
+    let mut badges = vec![];
+    let properties = [
+        ("GB", "B+", "B++"),
+        ("GW", "W+", "W++"),
+        ("DM", "=", "=="),
+        ("UC", "~", "~~"),
+        ("HO", "hot", "HOT"),
+    ];
+    for &(id, normal, very) in properties.iter() {
+        match get_emphasis(node, id) {
+            Some(Emphasis::Normal) => badges.push(normal),
+            Some(Emphasis::Very) => badges.push(very),
+            None => {}
+        }
+    }
+    badges
+}
 
-                    for pos in neighbors {
-                        if board[pos] == 1 {
-                            let a = white_connections.add_node(y * width + x);
-                            let b = white_connections.add_node(pos);
-                            let e = white_connections.add_edge(a, b, 1);
-                        }
-                    }
+// V[] is positive if the position is good for black
+fn value_bar(value: f32) -> String {
+    let half = 10;
+    let range = 30.0;
+    let filled = ((value.abs() / range).min(1.0) * half as f32).round() as usize;
+    let mut bar = String::new();
+    for i in 0..half {
+        bar.push(if value < 0.0 && half - i <= filled { '#' } else { ' ' });
+    }
+    bar.push('|');
+    for i in 0..half {
+        bar.push(if value > 0.0 && i < filled { '#' } else { ' ' });
+    }
+    format!("W [{}] B {:+}", bar, value)
+}
 
-                    next_player = GoColor::Black;
-                }
-                Move((x, y), GoColor::Black) => {
-                    board[y * width + x] = 2;
-                    let mut neighbors = vec![];
-                    if x > 0 {
-                        neighbors.push(y * width + x.checked_sub(1).unwrap());
-                        if y > 0 {
-                            neighbors.push(
-                                y.checked_sub(1).unwrap() * width + x.checked_sub(1).unwrap(),
-                            );
-                        }
-                    }
-                    if y > 0 {
-                        neighbors.push(y.checked_sub(1).unwrap() * width + x);
-                    }
-                    if x + 1 < width {
-                        neighbors.push(y * width + x + 1);
-                        if y + 1 < height {
-                            neighbors.push(y + 1 * width + x + 1);
-                        }
-                    }
-                    if y + 1 < height {
-                        neighbors.push(y + 1 * width + x);
-                    }
+fn show_badges(
+    node: &GameNode,
+    last: Option<((usize, usize), GoColor, Vec<MoveAnnotation>)>,
+    height: usize,
+) {
+    let mut status = vec![];
+    if let Some(((x, y), color, annotations)) = last {
+        let mut badge = String::new();
+        for a in annotations {
+            let fg = match a {
+                MoveAnnotation::Tesuji(_) => format!("{}", color::Fg(color::Green)),
+                MoveAnnotation::Bad(_) => format!("{}", color::Fg(color::Red)),
+                _ => format!("{}", color::Fg(color::Yellow)),
+            };
+            badge.push_str(&format!("{}{}{}", fg, move_badge(&a), color::Fg(color::Reset)));
+        }
+        let player = if color == GoColor::Black { "B" } else { "W" };
+        status.push(format!("{} {}{}", player, format_point(x, y, height), badge));
+    }
+    let badges = position_badges(node);
+    if !badges.is_empty() {
+        status.push(badges.join(" "));
+    }
+    if let Ok(v) = node.get_real("V") {
+        status.push(value_bar(v));
+    }
+    if !status.is_empty() {
+        println!("{}", status.join(" | "));
+    }
+}
 
-                    for pos in neighbors {
-                        if board[pos] == 2 {
-                            let a = black_connections.add_node(y * width + x);
-                            let b = black_connections.add_node(pos);
-                            let e = black_connections.add_edge(a, b, 1);
-                        }
-                    }
+// board coordinates as printed on a Go board, the letter I is skipped
+fn format_point(x: usize, y: usize, height: usize) -> String {
+    let columns = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+    match columns.chars().nth(x) {
+        Some(c) => format!("{}{}", c, height - y),
+        None => format!("({},{})", x, y),
+    }
+}
 
-                    next_player = GoColor::White;
-                }
-                Setup((x, y), PointSt::White) => {
-                    board[y * width + x] = 1;
-
-                    let mut neighbors = vec![];
-                    if x > 0 {
-                        neighbors.push(y * width + x.checked_sub(1).unwrap());
-                        if y > 0 {
-                            neighbors.push(
-                                y.checked_sub(1).unwrap() * width + x.checked_sub(1).unwrap(),
-                            );
-                        }
-                    }
-                    if y > 0 {
-                        neighbors.push(y.checked_sub(1).unwrap() * width + x);
-                    }
-                    if x + 1 < width {
-                        neighbors.push(y * width + x + 1);
-                        if y + 1 < height {
-                            neighbors.push(y + 1 * width + x + 1);
-                        }
-                    }
-                    if y + 1 < height {
-                        neighbors.push(y + 1 * width + x);
-                    }
+// replays the instructions, keeping track of the move numbers to show
+fn replay(
+    game: &Model,
+    instructions: &[Instruction],
+) -> (Position, Vec<Option<i32>>, Vec<String>) {
+    let width = game.width;
+    let height = game.height;
+    let mut position = Position::new(width, height);
 
-                    for pos in neighbors {
-                        if board[pos] == 1 {
-                            let a = white_connections.add_node(y * width + x);
-                            let b = white_connections.add_node(pos);
-                            let e = white_connections.add_edge(a, b, 1);
-                        }
-                    }
-                }
-                Setup((x, y), PointSt::Black) => {
-                    board[y * width + x] = 2;
-                    let mut neighbors = vec![];
-                    if x > 0 {
-                        neighbors.push(y * width + x.checked_sub(1).unwrap());
-                        if y > 0 {
-                            neighbors.push(
-                                y.checked_sub(1).unwrap() * width + x.checked_sub(1).unwrap(),
-                            );
-                        }
-                    }
-                    if y > 0 {
-                        neighbors.push(y.checked_sub(1).unwrap() * width + x);
-                    }
-                    if x + 1 < width {
-                        neighbors.push(y * width + x + 1);
-                        if y + 1 < height {
-                            neighbors.push(y + 1 * width + x + 1);
-                        }
-                    }
-                    if y + 1 < height {
-                        neighbors.push(y + 1 * width + x);
-                    }
+    // move numbers of the stones on the board and "12 at 5" notes
+    let numbered_from = first_numbered_move(game, instructions);
+    let mut numbers: Vec<Option<i32>> = vec![None; width * height];
+    let mut first_numbers: Vec<Option<i32>> = vec![None; width * height];
+    let mut footnotes = vec![];
 
-                    for pos in neighbors {
-                        if board[pos] == 2 {
-                            let a = black_connections.add_node(y * width + x);
-                            let b = black_connections.add_node(pos);
-                            let e = black_connections.add_edge(a, b, 1);
+    for instr in instructions {
+        for pos in position.apply(instr) {
+            numbers[pos] = None;
+        }
+        match *instr {
+            Setup((x, y), _) if x < width && y < height => numbers[y * width + x] = None,
+            Move((x, y), _) if x < width && y < height => {
+                let pos = y * width + x;
+                let move_number = position.move_number;
+                numbers[pos] = None;
+                if numbered_from.is_some_and(|first| position.move_count >= first) {
+                    // as in printed records the point keeps the number of its
+                    // first move, the later ones are in the notes
+                    let number = match first_numbers[pos] {
+                        Some(first) => {
+                            footnotes.push(format!("{} at {}", move_number, first));
+                            first
+                        }
+                        None => {
+                            first_numbers[pos] = Some(move_number);
+                            move_number
                         }
+                    };
+                    // not if the move was a suicide
+                    if position.board[pos] != 0 {
+                        numbers[pos] = Some(number);
                     }
                 }
-                Setup((x, y), PointSt::Free) => board[y * width + x] = 0,
-                NextPlayer(color) => next_player = color,
-                i => panic!("Error: unexpected Instruction {:?}", i),
             }
+            _ => {}
         }
+    }
+    (position, numbers, footnotes)
+}
 
-        //compute scc's
-        if next_player == GoColor::White {
-            let mut hr = white_connections.into_edge_type::<petgraph::Undirected>();
-            let components = tarjan_scc(&hr);
-            for c in components {
-                println!("component:{:?}", c);
-            }
+impl GoColor {
+    fn opponent(self) -> GoColor {
+        match self {
+            GoColor::White => GoColor::Black,
+            GoColor::Black => GoColor::White,
         }
+    }
 
-        if next_player == GoColor::Black {
-            let mut hr = black_connections.into_edge_type::<petgraph::Undirected>();
-            let components = tarjan_scc(&hr);
-            for c in components {
-                println!("component:{:?}", c);
-            }
+    // the value of a stone of this color on the board
+    fn stone(self) -> u8 {
+        match self {
+            GoColor::White => 1,
+            GoColor::Black => 2,
         }
+    }
+}
 
-        for y in 0..(height) {
-            for x in 0..width {
-                match board[y * width + x] {
-                    0 => print!("+"),
-                    1 => print!(
-                        "{red}●{reset}",
-                        red = color::Fg(color::Red),
-                        reset = color::Fg(color::Reset)
-                    ),
-                    2 => print!(
-                        "{blue}●{reset}",
-                        blue = color::Fg(color::Blue),
-                        reset = color::Fg(color::Reset)
-                    ),
-                    _ => println!(
-                        "{red}Unknown player{reset}",
-                        red = color::Fg(color::Red),
-                        reset = color::Fg(color::Reset)
-                    ),
-                }
-            }
-            println!();
-        }
+#[derive(Clone)]
+struct Position {
+    width: usize,
+    height: usize,
+    board: Vec<u8>,
+    next_player: GoColor,
+    // stones captured by black and by white
+    black_prisoners: usize,
+    white_prisoners: usize,
+    move_count: usize,
+    move_number: i32,
+}
 
-        // Show current node annotations
-        if let Ok(node_name) = cur_node.get_simple_text("N") {
-            println!("Node name: {}", node_name);
-        }
-        if let Ok(comment) = cur_node.get_text("C") {
-            println!("Comment: {}", comment);
-        }
-        if let Ok(n) = cur_node.get_double("DM") {
-            println!("Even position! {}", n);
-        }
-        if let Ok(n) = cur_node.get_double("GB") {
-            println!("Position is good for black! {}", n);
-        }
-        if let Ok(n) = cur_node.get_double("GW") {
-            println!("Position is good for white! {}", n);
-        }
-        if let Ok(n) = cur_node.get_double("HO") {
-            println!("Hotspot! {}", n);
-        }
-        if let Ok(n) = cur_node.get_double("UC") {
-            println!("Unclear position! {}", n);
-        }
-        if let Ok(n) = cur_node.get_double("V") {
-            println!("Value! {}", n);
+impl Position {
+    fn new(width: usize, height: usize) -> Position {
+        Position {
+            width,
+            height,
+            board: vec![0; width * height],
+            next_player: GoColor::Black,
+            black_prisoners: 0,
+            white_prisoners: 0,
+            move_count: 0,
+            move_number: 0,
         }
+    }
 
-        // Move annotations properties
-        if let Ok(_) = cur_node.get_double("BM") {
-            println!("Bad move!");
+    fn neighbors(&self, pos: usize) -> Vec<usize> {
+        let (x, y) = (pos % self.width, pos / self.width);
+        let mut neighbors = vec![];
+        if x > 0 {
+            neighbors.push(pos - 1);
         }
-        if let Ok(_) = cur_node.get_text("DO") {
-            println!("Doubtful move!");
+        if x + 1 < self.width {
+            neighbors.push(pos + 1);
         }
-        if let Ok(_) = cur_node.get_text("IT") {
-            println!("Interesting move!");
+        if y > 0 {
+            neighbors.push(pos - self.width);
         }
-        if let Ok(_) = cur_node.get_text("TE") {
-            println!("Tesuji!");
+        if y + 1 < self.height {
+            neighbors.push(pos + self.width);
         }
+        neighbors
+    }
 
-        // Markup properties
-        if let Ok(labels) = cur_node.get_points("LB") {
-            println!("labels: {:?}", labels);
-        }
-        if let Ok(points) = cur_node.get_points("MA") {
-            println!("mark x: {:?}", points);
-        }
-        if let Ok(points) = cur_node.get_points("CR") {
-            println!("circles: {:?}", points);
-        }
-        if let Ok(points) = cur_node.get_points("SQ") {
-            println!("squares: {:?}", points);
-        }
-        if let Ok(points) = cur_node.get_points("TR") {
-            println!("triangles: {:?}", points);
-        }
-        if let Ok(points) = cur_node.get_points("SL") {
-            println!("selected: {:?}", points);
-        }
-        if let Ok(points) = cur_node.get_points("DD") {
-            println!("DD dim: {:?}", points);
-        }
-        if let Ok(points) = cur_node.get_points("AR") {
-            println!("arrows: {:?}", points);
+    // the stones connected to pos and the number of their liberties
+    fn group(&self, pos: usize) -> (Vec<usize>, usize) {
+        let color = self.board[pos];
+        let mut stones = vec![pos];
+        let mut liberties = vec![];
+        let mut i = 0;
+        while i < stones.len() {
+            for n in self.neighbors(stones[i]) {
+                if self.board[n] == color && !stones.contains(&n) {
+                    stones.push(n);
+                } else if self.board[n] == 0 && !liberties.contains(&n) {
+                    liberties.push(n);
+                }
+            }
+            i += 1;
         }
-        if let Ok(points) = cur_node.get_points("LN") {
-            println!("lines: {:?}", points);
+        (stones, liberties.len())
+    }
+
+    // applies an instruction and returns the points of the removed stones
+    fn apply(&mut self, instr: &Instruction) -> Vec<usize> {
+        let mut removed = vec![];
+        match *instr {
+            Move((x, y), color) if x < self.width && y < self.height => {
+                self.move_count += 1;
+                self.move_number += 1;
+                let pos = y * self.width + x;
+                self.board[pos] = color.stone();
+                for n in self.neighbors(pos) {
+                    if self.board[n] == color.opponent().stone() && !removed.contains(&n) {
+                        let (stones, liberties) = self.group(n);
+                        if liberties == 0 {
+                            removed.extend(stones);
+                        }
+                    }
+                }
+                let captured = removed.len();
+                if removed.is_empty() {
+                    // suicide, the opponent gets the prisoners
+                    let (stones, liberties) = self.group(pos);
+                    if liberties == 0 {
+                        removed = stones;
+                    }
+                }
+                for p in &removed {
+                    self.board[*p] = 0;
+                }
+                match color {
+                    GoColor::Black => {
+                        self.black_prisoners += captured;
+                        self.white_prisoners += removed.len() - captured;
+                    }
+                    GoColor::White => {
+                        self.white_prisoners += captured;
+                        self.black_prisoners += removed.len() - captured;
+                    }
+                }
+                self.next_player = color.opponent();
+            }
+            // a move outside of the board, like tt in FF[3], is a pass
+            Move(_, color) | Pass(color) => {
+                self.move_count += 1;
+                self.move_number += 1;
+                self.next_player = color.opponent();
+            }
+            Setup((x, y), ref st) if x < self.width && y < self.height => {
+                self.board[y * self.width + x] = match *st {
+                    PointSt::White => 1,
+                    PointSt::Black => 2,
+                    PointSt::Free => 0,
+                };
+            }
+            Setup(_, _) => {}
+            NextPlayer(color) => self.next_player = color,
+            // the move in this node gets the given number
+            MoveNumber(n) => self.move_number = n - 1,
+            Annotate(_) => {}
         }
-    } else {
-        panic!("Error invalid path");
+        removed
     }
 }
 
-fn compute_black_groups() {}
-
-fn compute_white_groups() {}
-
 fn next_board(game: &mut Model) {
     game.path.push(0);
-    if let Some(cur_node) = traverse(game.node, &game.path) {
-
-    } else {
+    if traverse(&game.node, &game.path).is_none() {
         println!("Last node");
         game.path.pop();
     }
+    update_game_info(game);
 }
 
 fn alt_right(game: &mut Model) {
-    if let Some(mut last) = game.path.pop() {
-        if let Some(node) = traverse(game.node, &game.path) {
+    if let Some(last) = game.path.pop() {
+        if let Some(node) = traverse(&game.node, &game.path) {
             if last + 1 < node.children.len() {
                 game.path.push(last + 1);
             } else {
@@ -479,23 +1035,26 @@ fn alt_right(game: &mut Model) {
             panic!("Invalid path");
         }
     }
+    update_game_info(game);
 }
 
 fn alt_left(game: &mut Model) {
-    if let Some(mut last) = game.path.pop() {
+    if let Some(last) = game.path.pop() {
         if last > 0 {
             game.path.push(last - 1);
         } else {
             game.path.push(0);
         }
     }
+    update_game_info(game);
 }
 
 fn previous_board(game: &mut Model) {
     game.path.pop();
+    update_game_info(game);
 }
 
-fn traverse<'a>(node: &'a SgfNode, path: &[usize]) -> Option<&'a SgfNode> {
+fn traverse<'a>(node: &'a GameNode, path: &[usize]) -> Option<&'a GameNode> {
     if let Some((first, elements)) = path.split_first() {
         if node.children.len() > *first {
             traverse(&node.children[*first], elements)
@@ -507,209 +1066,1697 @@ fn traverse<'a>(node: &'a SgfNode, path: &[usize]) -> Option<&'a SgfNode> {
     }
 }
 
-fn collect_moves<'a>(node: &'a SgfNode, path: &[usize]) -> Vec<Instruction> {
-    let mut moves = vec![];
+fn traverse_mut<'a>(node: &'a mut GameNode, path: &[usize]) -> Option<&'a mut GameNode> {
     if let Some((first, elements)) = path.split_first() {
         if node.children.len() > *first {
-            moves = collect_moves(&node.children[*first], elements)
+            traverse_mut(&mut node.children[*first], elements)
+        } else {
+            None
         }
+    } else {
+        Some(node)
     }
+}
 
-    // setup properties
-    if let Ok(list) = node.get_points("AW") {
-        for s in list {
-            let (x, y) = str_to_position(&s);
-            moves.push(Setup((x, y), PointSt::White))
-        }
+// plays a stone for the player to move at the current node. If the node
+// already has a child with this move we follow it, otherwise a new
+// variation is created.
+fn play_move(game: &mut Model, (x, y): (usize, usize)) -> Result<(), String> {
+    let position = replay(game, &collect_moves(&game.node, &game.path)).0;
+    if x >= game.width || y >= game.height {
+        return Err(format!("({},{}) is not on the board", x, y));
     }
-    if let Ok(list) = node.get_points("AB") {
-        for s in list {
-            let (x, y) = str_to_position(&s);
-            moves.push(Setup((x, y), PointSt::Black))
-        }
+    if position.board[y * game.width + x] != 0 {
+        return Err(format!("{} is occupied", format_point(x, y, game.height)));
     }
-    if let Ok(list) = node.get_points("AE") {
-        for s in list {
-            let (x, y) = str_to_position(&s);
-            moves.push(Setup((x, y), PointSt::Free))
-        }
+    let mut after = position.clone();
+    after.apply(&Move((x, y), position.next_player));
+    if after.board[y * game.width + x] == 0 {
+        return Err(format!("{} is suicide", format_point(x, y, game.height)));
     }
-    if let Ok(c) = node.get_color("PL") {
-        //TODO detect color
-        println!("set next player {}", c);
-        moves.push(NextPlayer(GoColor::White))
+    // taking a ko back at once repeats the position before the last move
+    if let Some((_, path)) = game.path.split_last() {
+        let before = replay(game, &collect_moves(&game.node, path)).0;
+        if before.board == after.board {
+            return Err(format!("{} retakes the ko", format_point(x, y, game.height)));
+        }
+    }
+    let id = match position.next_player {
+        GoColor::Black => "B",
+        GoColor::White => "W",
+    };
+    let point = position_to_str(x, y);
+
+    let node = traverse(&game.node, &game.path).expect("Error invalid path");
+    let existing = node.children
+        .iter()
+        .position(|child| child.get_point(id).ok().as_ref() == Some(&point));
+    match existing {
+        Some(index) => {
+            game.path.push(index);
+            update_game_info(game);
+            Ok(())
+        }
+        None => {
+            let description = format!("play {} at {}", id, format_point(x, y, game.height));
+            edit(game, description, |game| {
+                let mut child = GameNode::new();
+                child.set_point(id, point);
+                let node = current_node_mut(game);
+                node.children.push(child);
+                let index = node.children.len() - 1;
+                game.path.push(index);
+                Ok(())
+            })
+        }
+    }
+}
+
+// the node to add setup properties to, a new child if the current node
+// has a move, because setup and move properties must not be mixed
+fn setup_node(game: &mut Model) -> &mut GameNode {
+    let node = traverse_mut(&mut game.node, &game.path).expect("Error invalid path");
+    if node.get_point("B").is_ok() || node.get_point("W").is_ok() {
+        node.children.push(GameNode::new());
+        game.path.push(node.children.len() - 1);
+    }
+    traverse_mut(&mut game.node, &game.path).expect("Error invalid path")
+}
+
+// adds a stone of the color at the cursor, or removes the stone there
+fn setup_stone(game: &mut Model, color: GoColor) {
+    let (x, y) = game.cursor;
+    let pos = y * game.width + x;
+    let current = replay(game, &collect_moves(&game.node, &game.path)).0;
+    let target = if current.board[pos] == color.stone() {
+        0
+    } else {
+        color.stone()
+    };
+    let compress = game.node.get_number("FF").unwrap_or(1) >= 4;
+    setup_node(game);
+
+    // what the point was before this node
+    let parent = &game.path[..game.path.len().saturating_sub(1)];
+    let before = if game.path.is_empty() {
+        0
+    } else {
+        replay(game, &collect_moves(&game.node, parent)).0.board[pos]
+    };
+
+    let node = current_node_mut(game);
+    for id in &["AB", "AW", "AE"] {
+        let mut points = point_list(node, id);
+        if points.contains(&(x, y)) {
+            points.retain(|p| *p != (x, y));
+            set_or_remove(node, id, write_point_list(&points, compress));
+        }
+    }
+    if target != before {
+        let id = match target {
+            0 => "AE",
+            1 => "AW",
+            _ => "AB",
+        };
+        let mut points = point_list(node, id);
+        points.push((x, y));
+        set_or_remove(node, id, write_point_list(&points, compress));
+    }
+}
+
+fn set_player(game: &mut Model, color: Option<GoColor>) {
+    let node = setup_node(game);
+    match color {
+        Some(GoColor::Black) => node.set_property("PL", vec!["B".to_string()]),
+        Some(GoColor::White) => node.set_property("PL", vec!["W".to_string()]),
+        None => {
+            node.remove_property("PL");
+            node
+        }
+    };
+}
+
+// the values of a point list property, with rectangles of points written
+// like "aa:cc" if the file format allows it
+fn write_point_list(points: &[(usize, usize)], compress: bool) -> Vec<String> {
+    let mut points = points.to_vec();
+    points.sort_by_key(|&(x, y)| (y, x));
+    points.dedup();
+    if !compress {
+        return points.iter().map(|&(x, y)| position_to_str(x, y)).collect();
+    }
+    let mut used = vec![false; points.len()];
+    let index = |p: (usize, usize)| points.binary_search_by_key(&(p.1, p.0), |&(x, y)| (y, x));
+    let mut values = vec![];
+    for i in 0..points.len() {
+        if used[i] {
+            continue;
+        }
+        let (x0, y0) = points[i];
+        let free = |p: (usize, usize), used: &[bool]| index(p).map(|j| !used[j]).unwrap_or(false);
+        // grow the rectangle to the right, then down
+        let mut x1 = x0;
+        while free((x1 + 1, y0), &used) {
+            x1 += 1;
+        }
+        let mut y1 = y0;
+        while (x0..=x1).all(|x| free((x, y1 + 1), &used)) {
+            y1 += 1;
+        }
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                if let Ok(j) = index((x, y)) {
+                    used[j] = true;
+                }
+            }
+        }
+        if (x0, y0) == (x1, y1) {
+            values.push(position_to_str(x0, y0));
+        } else {
+            values.push(format!("{}:{}", position_to_str(x0, y0), position_to_str(x1, y1)));
+        }
+    }
+    values
+}
+
+// changes the properties of the current node or adds a child to it, like a
+// move, keeping what undoes it
+fn edit<F>(game: &mut Model, description: String, change: F) -> Result<(), String>
+where
+    F: FnOnce(&mut Model) -> Result<(), String>,
+{
+    let path = game.path.clone();
+    let node = traverse(&game.node, &path).expect("Error invalid path");
+    let (before, children) = (node.without_children(), node.children.len());
+    let result = change(game);
+    let node = traverse(&game.node, &path).expect("Error invalid path");
+    let change = if node.children.len() > children {
+        let mut child = path.clone();
+        child.push(children);
+        Change::Remove(child)
+    } else {
+        Change::Properties(path.clone(), before)
+    };
+    if let Err(m) = result {
+        apply(&mut game.node, change);
+        game.path = path;
+        return Err(m);
+    }
+    record(game, Edit { description, change, path });
+    Ok(())
+}
+
+// moves or removes a variation, the change gives the path after it
+fn edit_tree<F>(game: &mut Model, description: String, change: F) -> Result<(), String>
+where
+    F: FnOnce(&Model) -> Result<(Change, Vec<usize>), String>,
+{
+    let (change, path) = change(game)?;
+    let change = apply(&mut game.node, change);
+    let path = std::mem::replace(&mut game.path, path);
+    record(game, Edit { description, change, path });
+    Ok(())
+}
+
+fn record(game: &mut Model, edit: Edit) {
+    // the saved tree was undone and is replaced by this edit
+    if game.saved.is_some_and(|saved| saved > game.undo.len()) {
+        game.saved = None;
+    }
+    game.undo.push(edit);
+    game.redo.clear();
+    update_game_info(game);
+}
+
+// makes the change and returns the change that reverts it
+fn apply(root: &mut GameNode, change: Change) -> Change {
+    match change {
+        Change::Properties(path, mut node) => {
+            traverse_mut(root, &path)
+                .expect("Error invalid path")
+                .swap_properties(&mut node);
+            Change::Properties(path, node)
+        }
+        Change::Insert(path, node) => {
+            let (&index, parent) = path.split_last().expect("Error the root has no parent");
+            let parent = traverse_mut(root, parent).expect("Error invalid path");
+            parent.children.insert(index, node);
+            Change::Remove(path)
+        }
+        Change::Remove(path) => {
+            let (&index, parent) = path.split_last().expect("Error the root has no parent");
+            let parent = traverse_mut(root, parent).expect("Error invalid path");
+            let node = parent.children.remove(index);
+            Change::Insert(path, node)
+        }
+        Change::Move(path, to) => {
+            let (&index, parent) = path.split_last().expect("Error the root has no parent");
+            let mut moved = parent.to_vec();
+            moved.push(to);
+            let children = &mut traverse_mut(root, parent).expect("Error invalid path").children;
+            let child = children.remove(index);
+            children.insert(to, child);
+            Change::Move(moved, index)
+        }
+    }
+}
+
+fn undo(game: &mut Model) -> Result<(), String> {
+    let last = game.undo.pop().ok_or("nothing to undo")?;
+    let undone = swap_edit(game, last);
+    game.redo.push(undone);
+    Ok(())
+}
+
+fn redo(game: &mut Model) -> Result<(), String> {
+    let last = game.redo.pop().ok_or("nothing to redo")?;
+    let redone = swap_edit(game, last);
+    game.undo.push(redone);
+    Ok(())
+}
+
+// reverts the change of the edit and returns the edit to go back
+fn swap_edit(game: &mut Model, edit: Edit) -> Edit {
+    let change = apply(&mut game.node, edit.change);
+    let path = std::mem::replace(&mut game.path, edit.path);
+    update_game_info(game);
+    Edit {
+        description: edit.description,
+        change,
+        path,
+    }
+}
+
+// whether the tree differs from the one saved last
+fn is_dirty(game: &Model) -> bool {
+    game.saved != Some(game.undo.len())
+}
+
+fn show_edits(game: &Model) {
+    println!("{}", clear::All);
+    println!("History:");
+    for (i, edit) in game.undo.iter().enumerate() {
+        println!("{:>4} {}", i + 1, edit.description);
+    }
+    println!("   > now");
+    for (i, edit) in game.redo.iter().rev().enumerate() {
+        println!("{:>4} {} (undone)", game.undo.len() + i + 1, edit.description);
+    }
+}
+
+// the path of the parent of the current node and the index of the current
+// node
+fn parent(game: &Model) -> Result<(&[usize], usize), String> {
+    let (&index, parent) = game.path.split_last().ok_or("the root has no siblings")?;
+    Ok((parent, index))
+}
+
+// makes the current node the first child of its parent
+fn promote(game: &Model) -> Result<(Change, Vec<usize>), String> {
+    let (parent, _) = parent(game)?;
+    let mut path = parent.to_vec();
+    path.push(0);
+    Ok((Change::Move(game.path.clone(), 0), path))
+}
+
+// swaps the current node with its previous or next sibling
+fn move_variation(game: &Model, delta: isize) -> Result<(Change, Vec<usize>), String> {
+    let (parent, index) = parent(game)?;
+    let other = index as isize + delta;
+    let siblings = traverse(&game.node, parent).expect("Error invalid path").children.len();
+    if other < 0 || other as usize >= siblings {
+        return Err("no variation to swap with".to_string());
+    }
+    let mut path = parent.to_vec();
+    path.push(other as usize);
+    Ok((Change::Move(game.path.clone(), other as usize), path))
+}
+
+// removes the current node with all its children
+fn delete_variation(game: &Model) -> Result<(Change, Vec<usize>), String> {
+    let (parent, _) = parent(game)?;
+    Ok((Change::Remove(game.path.clone()), parent.to_vec()))
+}
+
+fn copy_variation(game: &mut Model) {
+    let node = traverse(&game.node, &game.path).expect("Error invalid path");
+    game.clipboard = Some(node.clone());
+}
+
+// adds the copied subtree as a new variation after the current node
+fn paste_variation(game: &mut Model) -> Result<(), String> {
+    let copy = game.clipboard.clone().ok_or("nothing copied")?;
+    let node = current_node_mut(game);
+    node.children.push(copy);
+    let index = node.children.len() - 1;
+    game.path.push(index);
+    Ok(())
+}
+
+fn move_cursor(game: &mut Model, dx: isize, dy: isize) {
+    let (x, y) = game.cursor;
+    let x = (x as isize + dx).max(0).min(game.width as isize - 1);
+    let y = (y as isize + dy).max(0).min(game.height as isize - 1);
+    game.cursor = (x as usize, y as usize);
+}
+
+fn collect_moves(node: &GameNode, path: &[usize]) -> Vec<Instruction> {
+    let mut moves = vec![];
+
+    // setup properties
+    for (x, y) in point_list(node, "AW") {
+        moves.push(Setup((x, y), PointSt::White))
+    }
+    for (x, y) in point_list(node, "AB") {
+        moves.push(Setup((x, y), PointSt::Black))
+    }
+    for (x, y) in point_list(node, "AE") {
+        moves.push(Setup((x, y), PointSt::Free))
+    }
+    if let Ok(c) = node.get_color("PL") {
+        match c {
+            'B' | 'b' => moves.push(NextPlayer(GoColor::Black)),
+            _ => moves.push(NextPlayer(GoColor::White)),
+        }
     }
 
-    // move properties
+    // move properties, malformed points are left out
+    if let Ok(n) = node.get_number("MN") {
+        moves.push(MoveNumber(n))
+    }
     if let Ok(s) = node.get_point("W") {
-        let (x, y) = str_to_position(&s);
-        moves.push(Move((x, y), GoColor::White))
+        if s.is_empty() {
+            moves.push(Pass(GoColor::White))
+        } else if let Some((x, y)) = str_to_position(&s) {
+            moves.push(Move((x, y), GoColor::White))
+        }
     }
     if let Ok(s) = node.get_point("B") {
-        let (x, y) = str_to_position(&s);
-        moves.push(Move((x, y), GoColor::Black))
+        if s.is_empty() {
+            moves.push(Pass(GoColor::Black))
+        } else if let Some((x, y)) = str_to_position(&s) {
+            moves.push(Move((x, y), GoColor::Black))
+        }
     }
-    if let Ok(_) = node.get_text("KO") {
+    if node.get_text("KO").is_ok() {
         //TODO set move status to illegal
     }
-    if let Ok(n) = node.get_number("MN") {
-        //TODO set move number
-    }
-    //TODO: Move annotations properties
-    //         if let Ok(_) = cur_node.get_double("BM"){
-    //           println!("Bad move!");
-    //         }
-    //         if let Ok(_) = cur_node.get_text("DO"){
-    //           println!("Doubtful move!");
-    //         }
-    //         if let Ok(_) = cur_node.get_text("IT"){
-    //           println!("Interesting move!");
-    //         }
-    //         if let Ok(_) = cur_node.get_text("TE"){
-    //           println!("Tesuji!");
-    //         }
+    // move annotations properties
+    if let Some(e) = get_emphasis(node, "BM") {
+        moves.push(Annotate(MoveAnnotation::Bad(e)))
+    }
+    if node.get_text("DO").is_ok() {
+        moves.push(Annotate(MoveAnnotation::Doubtful))
+    }
+    if node.get_text("IT").is_ok() {
+        moves.push(Annotate(MoveAnnotation::Interesting))
+    }
+    if let Some(e) = get_emphasis(node, "TE") {
+        moves.push(Annotate(MoveAnnotation::Tesuji(e)))
+    }
+
+    if let Some((first, elements)) = path.split_first() {
+        if node.children.len() > *first {
+            moves.append(&mut collect_moves(&node.children[*first], elements))
+        }
+    }
     moves
 }
 
-fn str_to_position(s: &str) -> (usize, usize) {
-    (
-        char2int(s.chars().nth(0).unwrap()),
-        char2int(s.chars().nth(1).unwrap()),
-    )
-}
-
-fn char2int(c: char) -> usize {
-    match c {
-        'a' => 0,
-        'b' => 1,
-        'c' => 2,
-        'd' => 3,
-        'e' => 4,
-        'f' => 5,
-        'g' => 6,
-        'h' => 7,
-        'i' => 8,
-        'j' => 9,
-        'k' => 10,
-        'l' => 11,
-        'm' => 12,
-        'n' => 13,
-        'o' => 14,
-        'p' => 15,
-        'q' => 16,
-        'r' => 17,
-        's' => 18,
-        't' => 19,
-        'u' => 20,
-        'v' => 21,
-        'w' => 22,
-        'x' => 23,
-        'y' => 24,
-        'z' => 25,
-        'A' => 26,
-        'B' => 27,
-        'C' => 28,
-        'D' => 29,
-        'E' => 30,
-        'F' => 31,
-        'G' => 32,
-        'H' => 33,
-        'I' => 34,
-        'J' => 35,
-        'K' => 36,
-        'L' => 37,
-        'M' => 38,
-        'N' => 39,
-        'O' => 40,
-        'P' => 41,
-        'Q' => 42,
-        'R' => 43,
-        'S' => 44,
-        'T' => 45,
-        'U' => 46,
-        'V' => 47,
-        'W' => 48,
-        'X' => 49,
-        'Y' => 50,
-        'Z' => 51,
-        _ => panic!("cannot handle coordinate {}", c),
+fn count_moves(instructions: &[Instruction]) -> usize {
+    instructions
+        .iter()
+        .filter(|i| matches!(**i, Move(_, _) | Pass(_)))
+        .count()
+}
+
+// the first move (counted from the root) that gets a number on the board
+fn first_numbered_move(game: &Model, instructions: &[Instruction]) -> Option<usize> {
+    match game.numbering {
+        Numbering::Off => None,
+        Numbering::All => Some(1),
+        Numbering::Last(n) => Some((count_moves(instructions) + 1).saturating_sub(n).max(1)),
+        Numbering::SinceBranch => {
+            // the last node on the path with more than one child
+            let mut branch = 0;
+            let mut node = &game.node;
+            for (depth, i) in game.path.iter().enumerate() {
+                if node.children.len() > 1 {
+                    branch = depth + 1;
+                }
+                node = &node.children[*i];
+            }
+            if branch == 0 {
+                return Some(1);
+            }
+            let before = collect_moves(&game.node, &game.path[..branch - 1]);
+            Some(count_moves(&before) + 1)
+        }
+    }
+}
+
+fn next_numbering(game: &mut Model) {
+    game.numbering = match game.numbering {
+        Numbering::Off => Numbering::All,
+        Numbering::All => Numbering::Last(10),
+        Numbering::Last(_) => Numbering::SinceBranch,
+        Numbering::SinceBranch => Numbering::Off,
+    };
+}
+
+fn change_numbered_moves(game: &mut Model, delta: isize) {
+    if let Numbering::Last(n) = game.numbering {
+        game.numbering = Numbering::Last((n as isize + delta).max(1) as usize);
+    }
+}
+
+fn next_tool(game: &mut Model) {
+    game.tool = match game.tool {
+        Tool::Stone => Tool::BlackStones,
+        Tool::BlackStones => Tool::WhiteStones,
+        Tool::WhiteStones => Tool::Letters,
+        Tool::Letters => Tool::Numbers,
+        Tool::Numbers => Tool::Triangle,
+        Tool::Triangle => Tool::Square,
+        Tool::Square => Tool::Circle,
+        Tool::Circle => Tool::Cross,
+        Tool::Cross => Tool::Selected,
+        Tool::Selected => Tool::Dimmed,
+        Tool::Dimmed => Tool::Arrow,
+        Tool::Arrow => Tool::Line,
+        Tool::Line => Tool::Stone,
+    };
+    game.tool_start = None;
+}
+
+fn tool_name(tool: Tool) -> &'static str {
+    match tool {
+        Tool::Stone => "stone",
+        Tool::BlackStones => "black stones",
+        Tool::WhiteStones => "white stones",
+        Tool::Letters => "letters",
+        Tool::Numbers => "numbers",
+        Tool::Triangle => "triangle",
+        Tool::Square => "square",
+        Tool::Circle => "circle",
+        Tool::Cross => "cross",
+        Tool::Selected => "selected",
+        Tool::Dimmed => "dimmed",
+        Tool::Arrow => "arrow",
+        Tool::Line => "line",
+    }
+}
+
+// adds or removes an arrow or line from the point to the cursor
+fn toggle_line(game: &mut Model, (sx, sy): (usize, usize)) {
+    let (x, y) = game.cursor;
+    let point = position_to_str(x, y);
+    let id = if game.tool == Tool::Arrow { "AR" } else { "LN" };
+    let from = position_to_str(sx, sy);
+    let node = current_node_mut(game);
+    let mut values = node.get_points(id).unwrap_or_default();
+    let same = |v: &String| {
+        *v == format!("{}:{}", from, point) || (id == "LN" && *v == format!("{}:{}", point, from))
+    };
+    if values.iter().any(same) {
+        values.retain(|v| !same(v));
+    } else {
+        values.push(format!("{}:{}", from, point));
+    }
+    set_or_remove(node, id, values);
+}
+
+// at most one of these may be on a point
+const MARKS: [&str; 5] = ["CR", "MA", "SQ", "TR", "LB"];
+
+// adds or removes the markup of the current tool at the cursor
+fn toggle_markup(game: &mut Model) {
+    let (x, y) = game.cursor;
+    let point = position_to_str(x, y);
+    let tool = game.tool;
+    let node = current_node_mut(game);
+    let id = match tool {
+        Tool::Letters | Tool::Numbers => "LB",
+        Tool::Triangle => "TR",
+        Tool::Square => "SQ",
+        Tool::Circle => "CR",
+        Tool::Cross => "MA",
+        Tool::Selected => "SL",
+        Tool::Dimmed => "DD",
+        _ => unreachable!(),
+    };
+    let at_point = |id: &str, node: &GameNode| {
+        if id == "LB" {
+            node.get_points(id)
+                .unwrap_or_default()
+                .iter()
+                .any(|v| v.split(':').next() == Some(&point[..]))
+        } else {
+            point_list(node, id).contains(&(x, y))
+        }
+    };
+    let was_set = at_point(id, node);
+    let exclusive: &[&str] = if MARKS.contains(&id) { &MARKS } else { &[id] };
+    for other in exclusive {
+        if at_point(other, node) {
+            remove_point(node, other, (x, y));
+        }
+    }
+    if !was_set {
+        if id == "LB" {
+            let mut labels = node.get_points("LB").unwrap_or_default();
+            labels.push(format!("{}:{}", point, next_label(&labels, tool)));
+            node.set_property("LB", labels);
+        } else {
+            let mut values = node.get_points(id).unwrap_or_default();
+            values.push(point);
+            node.set_property(id, values);
+        }
+    }
+}
+
+// the first letter or number not used by a label yet
+fn next_label(labels: &[String], tool: Tool) -> String {
+    let used: Vec<String> = labels
+        .iter()
+        .filter_map(|v| v.split_once(':').map(|(_, label)| label))
+        .map(decode_simple_text)
+        .collect();
+    let mut candidates: Box<dyn Iterator<Item = String>> = if tool == Tool::Numbers {
+        Box::new((1..).map(|n: usize| n.to_string()))
+    } else {
+        Box::new((b'A'..=b'Z').map(|c| (c as char).to_string()))
+    };
+    candidates
+        .find(|label| !used.contains(label))
+        .unwrap_or_else(|| "?".to_string())
+}
+
+fn remove_point(node: &mut GameNode, id: &str, (x, y): (usize, usize)) {
+    let point = position_to_str(x, y);
+    let values = if id == "LB" {
+        let mut labels = node.get_points(id).unwrap_or_default();
+        labels.retain(|v| v.split(':').next() != Some(&point[..]));
+        labels
+    } else {
+        point_list(node, id)
+            .into_iter()
+            .filter(|p| *p != (x, y))
+            .map(|(x, y)| position_to_str(x, y))
+            .collect()
+    };
+    set_or_remove(node, id, values);
+}
+
+fn set_or_remove(node: &mut GameNode, id: &str, values: Vec<String>) {
+    if values.is_empty() {
+        node.remove_property(id);
+    } else {
+        node.set_property(id, values);
+    }
+}
+
+// the points of a list property, with compressed rectangles like "aa:cc"
+// expanded
+fn point_list(node: &GameNode, id: &str) -> Vec<(usize, usize)> {
+    let mut points = vec![];
+    for value in node.get_points(id).unwrap_or_default() {
+        let mut corners = value.splitn(2, ':');
+        let first = corners.next().and_then(str_to_position);
+        match (first, corners.next().map(str_to_position)) {
+            (Some((ax, ay)), Some(Some((bx, by)))) => {
+                for y in ay.min(by)..=ay.max(by) {
+                    for x in ax.min(bx)..=ax.max(bx) {
+                        points.push((x, y));
+                    }
+                }
+            }
+            (Some(point), None) => points.push(point),
+            _ => {}
+        }
+    }
+    points
+}
+
+const LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+// the point of an SGF value like "dp", None for anything else
+fn str_to_position(s: &str) -> Option<(usize, usize)> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(x), Some(y), None) => Some((LETTERS.find(x)?, LETTERS.find(y)?)),
+        _ => None,
     }
 }
 
+fn position_to_str(x: usize, y: usize) -> String {
+    format!("{}{}", int2char(x), int2char(y))
+}
+
+fn int2char(i: usize) -> char {
+    LETTERS.chars().nth(i).expect("cannot handle coordinate")
+}
+
+// reads a point typed by the user, either as on the board like "D4" or
+// as in SGF like "dp"
+fn parse_point(s: &str, width: usize, height: usize) -> Option<(usize, usize)> {
+    let s = s.trim();
+    let column = s.chars().next()?;
+    let row = &s[column.len_utf8()..];
+    if let Ok(row) = row.parse::<usize>() {
+        let columns = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+        let x = columns.find(column.to_ascii_uppercase())?;
+        if row == 0 || row > height || x >= width {
+            return None;
+        }
+        return Some((x, height - row));
+    }
+    if s.len() == 2 && s.chars().all(|c| c.is_ascii_lowercase()) {
+        let (x, y) = str_to_position(s)?;
+        if x < width && y < height {
+            return Some((x, y));
+        }
+    }
+    None
+}
+
 fn main() {
     // iterator to the command line options
     let mut options = env::args();
     if let Some(x) = options.nth(1) {
-        // Create a path to the file
-        let path = Path::new(&x);
-        let display = path.display();
-
-        // Open the path in read-only mode, returns `io::Result<File>`
-        let mut file = match File::open(&path) {
-            // The `description` method of `io::Error` returns a string that
-            // describes the error
-            Err(why) => panic!("couldn't open {}: {}", display, why.description()),
-            Ok(file) => file,
+        // subcommands
+        let result = match x.as_str() {
+            "merge" => Some(merge::merge_files(&options.collect::<Vec<_>>())),
+            "clean" => Some(clean::clean_file(&options.collect::<Vec<_>>())),
+            "check" => Some(check::check_file(&options.collect::<Vec<_>>())),
+            "book" => Some(book::book_file(&options.collect::<Vec<_>>())),
+            "json" => Some(json::json_file(&options.collect::<Vec<_>>())),
+            "convert" => Some(import::convert_file(&options.collect::<Vec<_>>())),
+            "export" => Some(diagram::export_file(&options.collect::<Vec<_>>())),
+            "index" => Some(index::index_directory(&options.collect::<Vec<_>>())),
+            "search" => Some(index::search(&options.collect::<Vec<_>>())),
+            _ => None,
         };
+        if let Some(result) = result {
+            if let Err(why) = result {
+                eprintln!("{}", why);
+                process::exit(1);
+            }
+            return;
+        }
 
-        // Read the file contents into a string, returns `io::Result<usize>`
-        let mut s = String::new();
-        match file.read_to_string(&mut s) {
-            Err(why) => panic!("couldn't read {}: {}", display, why.description()),
-            Ok(_) => println!("{} read", display),
-        }
-
-        let c = SgfCollection::from_sgf(&s).expect("Error parsing SGF");
-
-        println!("collection of {} games: {}", c.len(), c);
-
-        if c.len() > 0 {
-            let sgf_game = c.first().unwrap();
-            let mut game_count = 1;
-            match get_board(&sgf_game) {
-                Ok(mut game) => {
-                    let mut ioin = io::stdin();
-                    for char in ioin.lock().chars() {
-                        show_board(&game);
-                        let c = char.unwrap();
-                        match c {
-                            'w' => {
-                                println!("You pressed char {:?}", c);
-                                previous_board(&mut game);
+        if Path::new(&x).is_dir() {
+            let mut input = io::stdin();
+            if let Err(why) = browser::browse_directory(Path::new(&x), &mut input) {
+                panic!("{}", why);
+            }
+            return;
+        }
+        let data = match read_file(&x) {
+            Ok(data) => data,
+            Err(why) => panic!("{}", why),
+        };
+        // the keys are read from the terminal when the game comes from stdin
+        let mut input: Box<dyn Read> = if x == "-" {
+            match termion::get_tty() {
+                Ok(tty) => Box::new(tty),
+                Err(why) => panic!("couldn't open the terminal: {}", why),
+            }
+        } else {
+            Box::new(io::stdin())
+        };
+        if archive::is_zip(&data) {
+            if let Err(why) = browser::browse_archive(&x, &data, &mut input) {
+                panic!("{}", why);
+            }
+            return;
+        }
+        let c = match parse_collection(&x, data) {
+            Ok(c) => c,
+            Err(why) => panic!("{}", why),
+        };
+        println!("{} read", x);
+        navigate(c, &x, 0, &mut input);
+    } else {
+        println!("Usage: sgf-reader filename");
+    }
+}
+
+// the navigator for a game of the collection read from the file, by its
+// index, changes are saved to the file of save_name
+fn navigate(mut c: Collection, source: &str, index: usize, input: &mut dyn Read) {
+    println!("collection of {} games", c.games.len());
+    if index >= c.games.len() {
+        println!("there is no game {} in {}", index + 1, source);
+        return;
+    } else if index > 0 {
+        println!("game {}", index + 1);
+    }
+    let mut file_name = save_name(source);
+    // another file is only replaced when it is named in :w
+    let mut keep_file = replaces_other_file(source);
+    if keep_file {
+        println!("{} already exists, :w filename saves the changes", file_name);
+    } else if file_name != source {
+        println!("changes are saved to {}", file_name);
+    }
+
+    let sgf_game = std::mem::replace(&mut c.games[index], GameNode::new());
+    match get_board(sgf_game) {
+        Ok(mut game) => {
+            let mut show_info = false;
+            let mut show_history = false;
+            // a command or coordinate typed after ':'
+            let mut typed: Option<String> = None;
+            let mut confirm_quit = false;
+            show_board(&game);
+            for key in input.keys() {
+                let key = key.unwrap();
+                let mut message = None;
+                if confirm_quit {
+                    if key == Key::Char('\n') {
+                        continue;
+                    }
+                    confirm_quit = false;
+                    match key {
+                        Key::Char('y') => {
+                            match write_game(&mut game, &mut c, index, &file_name, keep_file) {
+                                Ok(()) => break,
+                                Err(why) => message = Some(why),
                             }
-                            'a' => {
-                                println!("You pressed char {:?}", c);
-                                alt_left(&mut game);
+                        }
+                        Key::Char('n') => break,
+                        _ => {}
+                    }
+                } else if let Some(mut command) = typed.take() {
+                    match key {
+                        Key::Char('\n') => {}
+                        Key::Char(c) => {
+                            command.push(c);
+                            typed = Some(command);
+                            continue;
+                        }
+                        Key::Backspace => {
+                            command.pop();
+                            typed = Some(command);
+                            continue;
+                        }
+                        // the command is dropped and the board shown again
+                        Key::Esc => command.clear(),
+                        _ => {
+                            typed = Some(command);
+                            continue;
+                        }
+                    }
+                    match parse_command(&command, game.width, game.height) {
+                        Some(Command::Play(p)) => {
+                            if let Err(m) = play_move(&mut game, p) {
+                                message = Some(m);
+                            }
+                        }
+                        Some(Command::Comment(text)) => {
+                            message = edit(&mut game, "comment".to_string(), |g| {
+                                edit_comment(g, text)
+                            }).err()
+                        }
+                        Some(Command::AppendComment(text)) => {
+                            message = edit(&mut game, "comment".to_string(), |g| {
+                                append_comment(g, &text);
+                                Ok(())
+                            }).err()
+                        }
+                        Some(Command::Name(name)) => {
+                            message = edit(&mut game, "node name".to_string(), |g| {
+                                set_node_name(g, name);
+                                Ok(())
+                            }).err()
+                        }
+                        Some(Command::Annotate(id, emphasis)) => {
+                            let description = format!("annotation {}", id);
+                            message = edit(&mut game, description, |g| {
+                                toggle_annotation(g, id, emphasis)
+                            }).err()
+                        }
+                        Some(Command::PlayerToMove(color)) => {
+                            message = edit(&mut game, "player to move".to_string(), |g| {
+                                set_player(g, color);
+                                Ok(())
+                            }).err()
+                        }
+                        Some(Command::Promote) => {
+                            let description = "promote variation".to_string();
+                            message = edit_tree(&mut game, description, promote).err()
+                        }
+                        Some(Command::MoveUp) => {
+                            message = edit_tree(&mut game, "move variation up".to_string(), |g| {
+                                move_variation(g, -1)
+                            }).err()
+                        }
+                        Some(Command::MoveDown) => {
+                            let description = "move variation down".to_string();
+                            message = edit_tree(&mut game, description, |g| {
+                                move_variation(g, 1)
+                            }).err()
+                        }
+                        Some(Command::Delete) => {
+                            let description = "delete variation".to_string();
+                            message = edit_tree(&mut game, description, delete_variation).err()
+                        }
+                        Some(Command::Copy) => copy_variation(&mut game),
+                        Some(Command::Paste) => {
+                            let description = "paste variation".to_string();
+                            message = edit(&mut game, description, paste_variation).err()
+                        }
+                        Some(Command::Undo) => message = undo(&mut game).err(),
+                        Some(Command::Redo) => message = redo(&mut game).err(),
+                        Some(Command::Export(name, region)) => {
+                            message = Some(match export_position(&game, &name, region) {
+                                Ok(()) => format!("{} written", name),
+                                Err(why) => why,
+                            })
+                        }
+                        Some(Command::Diagram(format, region)) => {
+                            message = Some(match text_diagram(&game, format, region) {
+                                Ok(text) => text,
+                                Err(why) => why,
+                            })
+                        }
+                        Some(Command::Write(name)) => {
+                            if let Some(name) = name {
+                                file_name = name;
+                                keep_file = false;
                             }
-                            's' => {
-                                println!("You pressed char {:?}", c);
-                                next_board(&mut game);
+                            let result =
+                                write_game(&mut game, &mut c, index, &file_name, keep_file);
+                            message = Some(match result {
+                                Ok(()) => format!("{} written", file_name),
+                                Err(why) => why,
+                            });
+                        }
+                        Some(Command::WriteQuit) => {
+                            match write_game(&mut game, &mut c, index, &file_name, keep_file) {
+                                Ok(()) => break,
+                                Err(why) => message = Some(why),
                             }
-                            'd' => {
-                                println!("You pressed char {:?}", c);
-                                alt_right(&mut game);
+                        }
+                        Some(Command::Quit) if !is_dirty(&game) => break,
+                        Some(Command::Quit) => {
+                            message = Some("unsaved changes, :q! quits anyway".to_string())
+                        }
+                        Some(Command::ForceQuit) => break,
+                        None if command.trim().is_empty() => {}
+                        None => message = Some(format!("unknown command: {}", command)),
+                    }
+                } else {
+                    match key {
+                        Key::Char('w') => previous_board(&mut game),
+                        Key::Char('a') => alt_left(&mut game),
+                        Key::Char('s') => next_board(&mut game),
+                        Key::Char('d') => alt_right(&mut game),
+                        Key::Up => move_cursor(&mut game, 0, -1),
+                        Key::Down => move_cursor(&mut game, 0, 1),
+                        Key::Left => move_cursor(&mut game, -1, 0),
+                        Key::Right => move_cursor(&mut game, 1, 0),
+                        Key::Char('p') if game.tool == Tool::Stone => {
+                            let cursor = game.cursor;
+                            if let Err(m) = play_move(&mut game, cursor) {
+                                message = Some(m);
                             }
-                            'q' => {
-                                break;
+                        }
+                        Key::Char('p') => {
+                            let (x, y) = game.cursor;
+                            let description = format!(
+                                "{} at {}",
+                                tool_name(game.tool),
+                                format_point(x, y, game.height)
+                            );
+                            message = match (game.tool, game.tool_start.take()) {
+                                (Tool::Arrow, None) | (Tool::Line, None) => {
+                                    game.tool_start = Some((x, y));
+                                    None
+                                }
+                                (_, Some(start)) if start == (x, y) => None,
+                                (_, Some(start)) => edit(&mut game, description, |g| {
+                                    toggle_line(g, start);
+                                    Ok(())
+                                }).err(),
+                                (Tool::BlackStones, None) => edit(&mut game, description, |g| {
+                                    setup_stone(g, GoColor::Black);
+                                    Ok(())
+                                }).err(),
+                                (Tool::WhiteStones, None) => edit(&mut game, description, |g| {
+                                    setup_stone(g, GoColor::White);
+                                    Ok(())
+                                }).err(),
+                                _ => edit(&mut game, description, |g| {
+                                    toggle_markup(g);
+                                    Ok(())
+                                }).err(),
                             }
-                            _ => {}
                         }
+                        Key::Char('m') => next_tool(&mut game),
+                        Key::Char('u') => message = undo(&mut game).err(),
+                        Key::Char('r') => message = redo(&mut game).err(),
+                        Key::Char('h') => {
+                            show_history = !show_history;
+                        }
+                        Key::Char(':') => {
+                            typed = Some(String::new());
+                            continue;
+                        }
+                        Key::Char('i') => {
+                            show_info = !show_info;
+                        }
+                        Key::Char('n') => {
+                            next_numbering(&mut game);
+                        }
+                        Key::Char('+') => {
+                            change_numbered_moves(&mut game, 1);
+                        }
+                        Key::Char('-') => {
+                            change_numbered_moves(&mut game, -1);
+                        }
+                        Key::Char('q') if is_dirty(&game) => {
+                            confirm_quit = true;
+                            message = Some(format!(
+                                "Save changes to {}? (y)es, (n)o, (c)ancel",
+                                file_name
+                            ));
+                        }
+                        Key::Char('q') => {
+                            break;
+                        }
+                        _ => continue,
                     }
                 }
-                Err(m) => println!("some error: "),
+                if show_history {
+                    show_edits(&game);
+                } else if show_info {
+                    show_details(&game);
+                } else {
+                    show_board(&game);
+                }
+                if let Some(m) = message {
+                    println!("{}", m);
+                }
             }
-        } else {
-            println!("Empty SGF");
         }
+        Err(why) => println!("{}", why),
+    }
+}
+
+// reads and parses a file
+fn load_collection(file_name: &str) -> Result<Collection, String> {
+    parse_collection(file_name, read_file(file_name)?)
+}
+
+// the bytes of a file, of standard input for "-" or of a file in a zip
+// archive for a path like games.zip/game.sgf
+fn read_file(file_name: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    let member = archive::split(file_name);
+    let result = match member {
+        _ if file_name == "-" => io::stdin().read_to_end(&mut bytes),
+        Some((archive_name, _)) => File::open(Path::new(archive_name))
+            .and_then(|mut file| file.read_to_end(&mut bytes)),
+        None => File::open(Path::new(file_name)).and_then(|mut file| file.read_to_end(&mut bytes)),
+    };
+    result.map_err(|why| format!("couldn't read {}: {}", file_name, why))?;
+    match member {
+        Some((_, name)) => {
+            let entries = archive::entries(&bytes)?;
+            let entry = entries
+                .iter()
+                .find(|entry| entry.name == name)
+                .ok_or(format!("{} not found", file_name))?;
+            archive::extract(&bytes, entry)
+        }
+        None => Ok(bytes),
+    }
+}
+
+// parses the bytes of a file in SGF or one of the imported formats
+fn parse_collection(file_name: &str, bytes: Vec<u8>) -> Result<Collection, String> {
+    let s = read_text(file_name, bytes)?;
+    Collection::from_sgf(&s).map_err(|e| format!("Error parsing {}: {}", file_name, e))
+}
+
+// the root node of the first game in a file, for a quick look at the game
+// information
+fn load_root(file_name: &str) -> Result<GameNode, String> {
+    let s = read_text(file_name, read_file(file_name)?)?;
+    GameNode::root_from_sgf(&s).map_err(|e| format!("Error parsing {}: {}", file_name, e))
+}
+
+// the SGF text of a file, gzip files are decompressed first
+fn read_text(file_name: &str, bytes: Vec<u8>) -> Result<String, String> {
+    let (file_name, bytes) = if deflate::is_gzip(&bytes) {
+        let bytes = deflate::gunzip(&bytes)
+            .map_err(|why| format!("couldn't read {}: {}", file_name, why))?;
+        (file_name.trim_end_matches(".gz"), bytes)
     } else {
-        println!("Usage: sgf-reader filename");
+        (file_name, bytes)
+    };
+    match import::detect(file_name, &bytes) {
+        Some(format) => import::to_sgf(format, &bytes),
+        None => decode(bytes),
+    }
+    .map_err(|why| format!("couldn't read {}: {}", file_name, why))
+}
+
+// the name a game is saved under: imported games are saved as SGF next to
+// the file, games from an archive next to the archive and games from stdin,
+// also those of an archive like "-/game.sgf", in the current directory
+fn save_name(file_name: &str) -> String {
+    let name = match archive::split(file_name) {
+        Some((archive_name, member)) => Path::new(archive_name)
+            .with_file_name(Path::new(member).file_name().unwrap_or_default())
+            .to_string_lossy()
+            .into_owned(),
+        None if file_name == "-" => "stdin.sgf".to_string(),
+        None if file_name.starts_with("-/") => Path::new(file_name)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        None => file_name.to_string(),
+    };
+    match name.strip_suffix(".gz") {
+        Some(sgf) if import::save_name(sgf) != sgf => import::save_name(sgf),
+        _ => import::save_name(&name),
+    }
+}
+
+// whether saving the game read from the file would replace another file,
+// like a game of the same name next to the archive it was read from
+fn replaces_other_file(file_name: &str) -> bool {
+    let target = save_name(file_name);
+    (target != file_name || file_name.starts_with("-/")) && Path::new(&target).exists()
+}
+
+// the text of a file in UTF-8 or in the charset given by CA, like Latin-1,
+// GB2312, Shift_JIS, EUC-KR or Big5, and in Latin-1 without CA as in SGF
+fn decode(bytes: Vec<u8>) -> Result<String, String> {
+    let bytes = match String::from_utf8(bytes) {
+        Ok(s) => return Ok(s),
+        Err(e) => e.into_bytes(),
+    };
+    // every byte is a character in Latin-1, enough to read CA from the root
+    let latin1: String = bytes.iter().map(|&b| b as char).collect();
+    let charset = GameNode::root_from_sgf(&latin1)
+        .ok()
+        .and_then(|root| root.get_simple_text("CA").ok())
+        .map(|charset| charset.trim().to_uppercase())
+        .unwrap_or_default();
+    match charset.as_str() {
+        "" | "ISO-8859-1" | "ISO8859-1" | "LATIN1" | "LATIN-1" => return Ok(latin1),
+        _ => {}
+    }
+    let encoding = encoding_rs::Encoding::for_label(charset.as_bytes())
+        .ok_or(format!("the charset {} is not supported", charset))?;
+    encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .map(|text| text.into_owned())
+        .ok_or(format!("the file is not valid {}", charset))
+}
+
+enum Command {
+    Play((usize, usize)),
+    // None opens the comment in $EDITOR
+    Comment(Option<String>),
+    AppendComment(String),
+    Name(Option<String>),
+    Annotate(&'static str, Emphasis),
+    // None removes PL
+    PlayerToMove(Option<GoColor>),
+    Promote,
+    MoveUp,
+    MoveDown,
+    Delete,
+    Copy,
+    Paste,
+    Undo,
+    Redo,
+    // the file name and the region of the board to show
+    Export(String, Option<String>),
+    // a text diagram shown below the board, the format and the region
+    Diagram(&'static str, Option<String>),
+    Write(Option<String>),
+    WriteQuit,
+    Quit,
+    ForceQuit,
+}
+
+// commands typed after ':', a point plays a stone there
+fn parse_command(s: &str, width: usize, height: usize) -> Option<Command> {
+    let s = s.trim();
+    let mut words = s.splitn(2, ' ');
+    match (words.next(), words.next().map(|w| w.trim())) {
+        (Some("C"), None) => Some(Command::Comment(None)),
+        (Some("C"), Some(text)) => Some(Command::Comment(Some(text.to_string()))),
+        (Some("C+"), Some(text)) => Some(Command::AppendComment(text.to_string())),
+        (Some("N"), None) => Some(Command::Name(None)),
+        (Some("N"), Some(name)) => Some(Command::Name(Some(name.to_string()))),
+        (Some("PL"), None) => Some(Command::PlayerToMove(None)),
+        (Some("PL"), Some("B")) | (Some("PL"), Some("b")) => {
+            Some(Command::PlayerToMove(Some(GoColor::Black)))
+        }
+        (Some("PL"), Some("W")) | (Some("PL"), Some("w")) => {
+            Some(Command::PlayerToMove(Some(GoColor::White)))
+        }
+        (Some(word), None)
+            if word.len() <= 3 && ANNOTATIONS.iter().any(|a| word.starts_with(a)) =>
+        {
+            // e.g. TE, TE1 or TE2
+            let (id, emphasis) = match word.split_at(2) {
+                (id, "") | (id, "1") => (id, Emphasis::Normal),
+                (id, "2") => (id, Emphasis::Very),
+                _ => return None,
+            };
+            match id {
+                "DO" | "IT" if emphasis == Emphasis::Very => None,
+                _ => ANNOTATIONS
+                    .iter()
+                    .find(|a| **a == id)
+                    .map(|a| Command::Annotate(a, emphasis)),
+            }
+        }
+        (Some("promote"), None) => Some(Command::Promote),
+        (Some("up"), None) => Some(Command::MoveUp),
+        (Some("down"), None) => Some(Command::MoveDown),
+        (Some("delete"), None) => Some(Command::Delete),
+        (Some("copy"), None) => Some(Command::Copy),
+        (Some("paste"), None) => Some(Command::Paste),
+        (Some("undo"), None) => Some(Command::Undo),
+        (Some("redo"), None) => Some(Command::Redo),
+        (Some("export"), Some(args)) => {
+            let mut args = args.split_whitespace();
+            let name = args.next()?.to_string();
+            Some(Command::Export(name, args.next().map(|r| r.to_string())))
+        }
+        (Some("diagram"), Some(args)) => {
+            let mut args = args.split_whitespace();
+            let format = match args.next()? {
+                "sensei" => "sensei",
+                "unicode" => "unicode",
+                _ => return None,
+            };
+            Some(Command::Diagram(format, args.next().map(|r| r.to_string())))
+        }
+        (Some("w"), None) => Some(Command::Write(None)),
+        (Some("w"), Some(name)) if !name.is_empty() => Some(Command::Write(Some(name.to_string()))),
+        (Some("wq"), None) => Some(Command::WriteQuit),
+        (Some("q"), None) => Some(Command::Quit),
+        (Some("q!"), None) => Some(Command::ForceQuit),
+        // commands come first, on large boards "up" or "wq" are points too
+        _ => parse_point(s, width, height).map(Command::Play),
+    }
+}
+
+fn current_node_mut(game: &mut Model) -> &mut GameNode {
+    traverse_mut(&mut game.node, &game.path).expect("Error invalid path")
+}
+
+fn edit_comment(game: &mut Model, text: Option<String>) -> Result<(), String> {
+    let text = match text {
+        Some(text) => text,
+        None => {
+            let comment = current_node_mut(game).get_text("C").unwrap_or_default();
+            edit_in_editor(&comment).map_err(|why| format!("couldn't edit the comment: {}", why))?
+        }
+    };
+    let node = current_node_mut(game);
+    let text = text.trim_end();
+    if text.is_empty() {
+        node.remove_property("C");
+    } else {
+        node.set_text("C", text);
+    }
+    Ok(())
+}
+
+fn append_comment(game: &mut Model, text: &str) {
+    let node = current_node_mut(game);
+    let comment = match node.get_text("C") {
+        Ok(comment) => format!("{}\n{}", comment, text),
+        Err(_) => text.to_string(),
+    };
+    node.set_text("C", &comment);
+}
+
+// opens the text in $EDITOR and returns it after the editor is closed
+fn edit_in_editor(text: &str) -> io::Result<String> {
+    let editor = env::var("EDITOR")
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "$EDITOR is not set"))?;
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+    let file_name = env::temp_dir().join(format!("sgf-navigator-{}.txt", process::id()));
+    File::create(&file_name)?.write_all(text.as_bytes())?;
+    let status = process::Command::new(program)
+        .args(args)
+        .arg(&file_name)
+        .status();
+    let mut edited = String::new();
+    let read = File::open(&file_name).and_then(|mut file| file.read_to_string(&mut edited));
+    let _ = fs::remove_file(&file_name);
+    if !status?.success() {
+        return Err(io::Error::other(format!("{} failed", program)));
+    }
+    read?;
+    Ok(edited)
+}
+
+fn set_node_name(game: &mut Model, name: Option<String>) {
+    let node = current_node_mut(game);
+    match name {
+        Some(name) => node.set_simple_text("N", &name),
+        None => {
+            node.remove_property("N");
+            node
+        }
+    };
+}
+
+const ANNOTATIONS: [&str; 9] = ["BM", "DO", "IT", "TE", "GB", "GW", "DM", "UC", "HO"];
+
+// sets an annotation of the current node, or removes it if it is already set
+fn toggle_annotation(game: &mut Model, id: &str, emphasis: Emphasis) -> Result<(), String> {
+    let node = current_node_mut(game);
+    let is_move_annotation = MOVE_ANNOTATIONS.contains(&id);
+    if is_move_annotation && node.get_point("B").is_err() && node.get_point("W").is_err() {
+        return Err("move annotations need a move in the node".to_string());
+    }
+    if get_emphasis(node, id) == Some(emphasis) {
+        node.remove_property(id);
+    } else {
+        let group: &[&str] = if is_move_annotation {
+            &MOVE_ANNOTATIONS
+        } else if POSITION_ANNOTATIONS.contains(&id) {
+            &POSITION_ANNOTATIONS
+        } else {
+            &[]
+        };
+        for other in group {
+            node.remove_property(other);
+        }
+        match (id, emphasis) {
+            ("DO", _) | ("IT", _) => node.set_property(id, vec![String::new()]),
+            (_, Emphasis::Normal) => node.set_double(id, '1'),
+            (_, Emphasis::Very) => node.set_double(id, '2'),
+        };
     }
+    Ok(())
+}
+
+// the corners of a region typed like "A19:K10", None for the whole board
+fn parse_crop(
+    game: &Model,
+    region: Option<String>,
+) -> Result<Option<(diagram::Point, diagram::Point)>, String> {
+    match region {
+        Some(region) => diagram::parse_region(&region, game.width, game.height)
+            .map(Some)
+            .ok_or(format!("invalid region {}", region)),
+        None => Ok(None),
+    }
+}
+
+// writes a diagram of the current position, the format is given by the
+// extension of the file name
+fn export_position(game: &Model, file_name: &str, region: Option<String>) -> Result<(), String> {
+    let format = match Path::new(file_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .as_str()
+    {
+        "txt" => "sensei".to_string(),
+        extension => extension.to_string(),
+    };
+    let crop = parse_crop(game, region)?;
+    let output = diagram::export_game(game, crop, &format, &diagram::Style::default())?;
+    File::create(file_name)
+        .and_then(|mut file| file.write_all(&output))
+        .map_err(|why| format!("couldn't write {}: {}", file_name, why))
+}
+
+// the position as text to copy from the terminal
+fn text_diagram(game: &Model, format: &str, region: Option<String>) -> Result<String, String> {
+    let crop = parse_crop(game, region)?;
+    let output = diagram::export_game(game, crop, format, &diagram::Style::default())?;
+    Ok(String::from_utf8_lossy(&output).trim_end().to_string())
+}
+
+// writes the collection with the edited game, the one at the index, to a
+// file, compressed for names ending with .gz
+fn save(
+    game: &mut Model,
+    collection: &mut Collection,
+    index: usize,
+    file_name: &str,
+) -> io::Result<()> {
+    std::mem::swap(&mut collection.games[index], &mut game.node);
+    // the text is written in UTF-8, files without CA are read as Latin-1
+    let ascii = collection.to_string().is_ascii();
+    for root in &mut collection.games {
+        match root.get_simple_text("CA") {
+            Ok(ref charset) if charset.trim().eq_ignore_ascii_case("UTF-8") => {}
+            Err(_) if ascii => {}
+            _ => {
+                root.set_simple_text("CA", "UTF-8");
+            }
+        }
+    }
+    let mut bytes = collection.to_string().into_bytes();
+    if file_name.ends_with(".gz") {
+        bytes = deflate::gzip(&bytes);
+    }
+    let result = File::create(file_name).and_then(|mut file| file.write_all(&bytes));
+    std::mem::swap(&mut collection.games[index], &mut game.node);
+    if result.is_ok() {
+        game.saved = Some(game.undo.len());
+    }
+    result
+}
+
+// saves the game unless the file is kept, which is reported instead
+fn write_game(
+    game: &mut Model,
+    collection: &mut Collection,
+    index: usize,
+    file_name: &str,
+    keep_file: bool,
+) -> Result<(), String> {
+    if keep_file {
+        return Err(format!("{} already exists, use :w filename", file_name));
+    }
+    save(game, collection, index, file_name)
+        .map_err(|why| format!("couldn't write {}: {}", file_name, why))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn test_format_game_info() {
+        assert_eq!(format_date("1998-06-15"), "15 June 1998");
+        assert_eq!(
+            format_date("1996-12-27,28,1997-01-03"),
+            "27 December 1996, 28 December 1996, 3 January 1997"
+        );
+        assert_eq!(format_date("Summer 1998"), "Summer 1998");
+        assert_eq!(format_time(600.0), "10min per player");
+        assert_eq!(format_over_time("5x30 byo-yomi"), "byo-yomi, 5 periods of 30s");
+        assert_eq!(format_result("W+12.5"), "White wins by 12.5 points");
+        assert_eq!(format_result("B"), "Black wins");
+        assert_eq!(format_result("W+"), "White wins");
+    }
+
+    #[test]
+    fn test_badges() {
+        let mut c = Collection::from_sgf("(;FF[4]GM[1]SZ[9];B[ee]TE[2]GB[1]V[-4])").unwrap();
+        let root = c.games.remove(0);
+        let instructions = collect_moves(&root, &[0]);
+        let (pos, color, annotations) = last_move(&instructions).unwrap();
+        assert_eq!((pos, color), ((4, 4), GoColor::Black));
+        assert_eq!(move_badge(&annotations[0]), "!!");
+        assert_eq!(position_badges(&root.children[0]), vec!["B+"]);
+        assert_eq!(value_bar(-4.0), "W [         #|          ] B -4");
+
+        // the badge is the one of the last move on the path, not the first
+        let mut c = Collection::from_sgf("(;FF[4]GM[1]SZ[9];B[ee]TE[1];W[gg]BM[1])").unwrap();
+        let root = c.games.remove(0);
+        let instructions = collect_moves(&root, &[0, 0]);
+        let (pos, color, annotations) = last_move(&instructions).unwrap();
+        assert_eq!((pos, color), ((6, 6), GoColor::White));
+        assert_eq!(move_badge(&annotations[0]), "?");
+    }
+
+    #[test]
+    fn test_first_numbered_move() {
+        let mut game = model("(;FF[4]GM[1]SZ[5];B[aa];W[](;B[cc];W[dd])(;B[ee]))");
+        game.path = vec![0, 0, 0, 0];
+        let instructions = collect_moves(&game.node, &game.path);
+        assert_eq!(count_moves(&instructions), 4);
+        match instructions[0] {
+            Move((0, 0), GoColor::Black) => {}
+            ref i => panic!("unexpected first instruction {:?}", i),
+        }
+        game.numbering = Numbering::Last(3);
+        assert_eq!(first_numbered_move(&game, &instructions), Some(2));
+        game.numbering = Numbering::SinceBranch;
+        assert_eq!(first_numbered_move(&game, &instructions), Some(3));
+    }
+
+    #[test]
+    fn test_captures() {
+        // black captures the white stone at ba, white retakes at ba
+        let mut game = model("(;FF[4]GM[1]SZ[5]AB[ca][bb]AW[ab];W[ba];B[aa];W[ba])");
+        game.numbering = Numbering::All;
+
+        game.path = vec![0, 0];
+        let (position, numbers, footnotes) = replay(&game, &collect_moves(&game.node, &game.path));
+        assert_eq!(position.black_prisoners, 1);
+        assert_eq!(position.board[1], 0);
+        assert_eq!(numbers[0], Some(2));
+        assert!(footnotes.is_empty());
+
+        game.path = vec![0, 0, 0];
+        let (position, numbers, footnotes) = replay(&game, &collect_moves(&game.node, &game.path));
+        assert_eq!(position.white_prisoners, 1);
+        assert_eq!(numbers[0], None);
+        // the stone of move 3 shows the 1 of the note
+        assert_eq!(numbers[1], Some(1));
+        assert_eq!(footnotes, vec!["3 at 1"]);
+    }
+
+    #[test]
+    fn test_play_move() {
+        // white took the ko at B4, black can not take it back at once
+        let mut game = model("(;FF[4]GM[1]SZ[5]AB[ba][ab][bc][ad][be]AW[ca][db][cc];B[cb];W[bb])");
+        game.path = vec![0, 0];
+        assert_eq!(play_move(&mut game, (2, 1)), Err("C4 retakes the ko".to_string()));
+        assert!(play_move(&mut game, (3, 3)).is_ok());
+        assert_eq!(play_move(&mut game, (0, 4)), Err("A1 is suicide".to_string()));
+        assert!(play_move(&mut game, (4, 4)).is_ok());
+        // a move that captures is no suicide
+        assert!(play_move(&mut game, (2, 1)).is_ok());
+        assert_eq!(game.path, vec![0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_game_info_node() {
+        let c = Collection::from_sgf(
+            "(;FF[4]GM[1]SZ[9](;PB[alice];B[ee];W[cc])(;PB[carol];B[cc]))",
+        ).unwrap();
+        let root = &c.games[0];
+        assert_eq!(game_info_node(root, &[]).get_text("PB").unwrap(), "alice");
+        assert_eq!(game_info_node(root, &[1, 0]).get_text("PB").unwrap(), "carol");
+    }
+
+    #[test]
+    fn test_annotations() {
+        let mut game = model("(;FF[4]GM[1]SZ[9];B[ee]TE[1])");
+        assert!(toggle_annotation(&mut game, "BM", Emphasis::Normal).is_err());
+        game.path = vec![0];
+        toggle_annotation(&mut game, "BM", Emphasis::Very).unwrap();
+        toggle_annotation(&mut game, "GB", Emphasis::Normal).unwrap();
+        assert_eq!(format!("{:?}", game.node.children[0]), "{ B[ee] BM[2] GB[1] }");
+        toggle_annotation(&mut game, "BM", Emphasis::Very).unwrap();
+        assert!(parse_command("TE2", 9, 9).is_some());
+        assert!(parse_command("DO2", 9, 9).is_none());
+        // short commands are not taken for annotations
+        assert!(matches!(parse_command("q", 9, 9), Some(Command::Quit)));
+        assert!(matches!(parse_command("w", 9, 9), Some(Command::Write(None))));
+        assert!(matches!(parse_command("up", 9, 9), Some(Command::MoveUp)));
+        assert!(matches!(parse_command("up", 25, 25), Some(Command::MoveUp)));
+        assert!(matches!(parse_command("wq", 25, 25), Some(Command::WriteQuit)));
+        assert!(matches!(parse_command("ab", 25, 25), Some(Command::Play((0, 1)))));
+        assert_eq!(format!("{:?}", game.node.children[0]), "{ B[ee] GB[1] }");
+    }
+
+    #[test]
+    fn test_markup() {
+        let mut game = model("(;FF[4]GM[1]SZ[9]TR[aa:bb]LB[cc:A])");
+        game.tool = Tool::Letters;
+        game.cursor = (1, 1);
+        toggle_markup(&mut game);
+        game.tool = Tool::Square;
+        game.cursor = (2, 2);
+        toggle_markup(&mut game);
+        assert_eq!(
+            format!("{:?}", game.node),
+            "{ FF[4] GM[1] SZ[9] TR[aa][ba][ab] LB[bb:B] SQ[cc] }"
+        );
+        game.tool = Tool::Line;
+        game.cursor = (0, 0);
+        toggle_line(&mut game, (2, 2));
+        assert_eq!(game.node.get_points("LN").unwrap(), vec!["cc:aa"]);
+        game.cursor = (2, 2);
+        toggle_line(&mut game, (0, 0));
+        assert!(game.node.get_points("LN").is_err());
+    }
+
+    #[test]
+    fn test_setup_stones() {
+        let mut game = model("(;FF[4]GM[1]SZ[9]AB[aa:bb];B[ee])");
+        game.cursor = (2, 0);
+        setup_stone(&mut game, GoColor::Black);
+        game.cursor = (0, 1);
+        setup_stone(&mut game, GoColor::Black);
+        assert_eq!(game.node.get_points("AB").unwrap(), vec!["aa:ca", "bb"]);
+        assert_eq!(str_to_position("e1"), None);
+        let c = Collection::from_sgf("(;AB[e1][ab:c])").unwrap();
+        assert!(point_list(&c.games[0], "AB").is_empty());
+
+        game.path = vec![0];
+        game.cursor = (4, 4);
+        setup_stone(&mut game, GoColor::Black);
+        game.cursor = (0, 0);
+        setup_stone(&mut game, GoColor::White);
+        set_player(&mut game, Some(GoColor::White));
+        assert_eq!(game.path, vec![0, 0]);
+        assert_eq!(
+            format!("{:?}", game.node.children[0].children[0]),
+            "{ AE[ee] AW[aa] PL[W] }"
+        );
+    }
+
+    #[test]
+    fn test_restructure() {
+        let mut game = model("(;GM[1]SZ[9](;B[aa];W[bb])(;B[cc])(;B[dd]))");
+        let mut c = Collection::from_sgf("(;)").unwrap();
+        game.path = vec![2];
+        edit_tree(&mut game, String::new(), promote).unwrap();
+        assert_eq!(game.path, vec![0]);
+        edit_tree(&mut game, String::new(), |g| move_variation(g, 1)).unwrap();
+        assert_eq!(game.path, vec![1]);
+        assert_eq!(
+            format!("{:?}", game.node.children),
+            "[{ B[aa] }, { B[dd] }, { B[cc] }]"
+        );
+        game.path = vec![0];
+        copy_variation(&mut game);
+        edit_tree(&mut game, String::new(), delete_variation).unwrap();
+        assert_eq!(game.path, vec![]);
+        game.path = vec![1];
+        edit(&mut game, String::new(), paste_variation).unwrap();
+        assert_eq!(game.path, vec![1, 0]);
+        c.games = vec![game.node.clone()];
+        assert_eq!(c.to_string(), "(;GM[1]SZ[9](;B[dd])(;B[cc];B[aa];W[bb]))");
+        assert!(edit_tree(&mut game, String::new(), delete_variation).is_ok());
+        for _ in 0..5 {
+            undo(&mut game).unwrap();
+        }
+        assert!(undo(&mut game).is_err());
+        assert_eq!(game.path, vec![2]);
+        redo(&mut game).unwrap();
+        assert_eq!(game.path, vec![0]);
+        assert_eq!(game.undo[0].description, "");
+        assert_eq!(game.redo.len(), 4);
+        edit_tree(&mut game, String::new(), promote).unwrap();
+        assert!(game.redo.is_empty());
+        undo(&mut game).unwrap();
+        undo(&mut game).unwrap();
+        c.games = vec![game.node];
+        assert_eq!(c.to_string(), "(;GM[1]SZ[9](;B[aa];W[bb])(;B[cc])(;B[dd]))");
+    }
+
+    #[test]
+    fn test_undo() {
+        let sgf = "(;GM[1]SZ[9] ;B[aa] C[old])";
+        let mut game = model(sgf);
+        let mut c = Collection::from_sgf("(;)").unwrap();
+        game.path = vec![0];
+        edit(&mut game, String::new(), |g| edit_comment(g, Some("new".to_string()))).unwrap();
+        play_move(&mut game, (1, 1)).unwrap();
+        assert!(is_dirty(&game));
+        undo(&mut game).unwrap();
+        undo(&mut game).unwrap();
+        assert!(!is_dirty(&game));
+        // the nodes are written as they were read
+        c.games = vec![game.node.clone()];
+        assert_eq!(c.to_string(), sgf);
+
+        redo(&mut game).unwrap();
+        game.saved = Some(game.undo.len());
+        undo(&mut game).unwrap();
+        assert!(is_dirty(&game));
+        redo(&mut game).unwrap();
+        assert!(!is_dirty(&game));
+        // the saved tree can't be reached again after another edit
+        undo(&mut game).unwrap();
+        edit(&mut game, String::new(), |g| edit_comment(g, Some(String::new()))).unwrap();
+        assert_eq!(game.saved, None);
+        assert!(is_dirty(&game));
+    }
+
+    #[test]
+    fn test_decode() {
+        let text = |bytes: &[u8]| decode(bytes.to_vec());
+        assert_eq!(text(b"(;CA[Latin1]C[caf\xe9])").unwrap(), "(;CA[Latin1]C[café])");
+        assert_eq!(
+            text(b"(;CA[GB2312]C[\xce\xa7\xc6\xe5])").unwrap(),
+            "(;CA[GB2312]C[围棋])"
+        );
+        // the second byte of a Shift_JIS character may be a ]
+        assert_eq!(
+            text(b"(;CA[Shift_JIS]C[\x8c\xe9\x83\x5d])").unwrap(),
+            "(;CA[Shift_JIS]C[碁ゾ])"
+        );
+        assert!(text(b"(;CA[EUC-KR]C[\xff])").is_err());
+        assert!(text(b"(;CA[Klingon]C[\xff])").is_err());
+        assert_eq!(text(b"(;C[\xff])").unwrap(), "(;C[\u{ff}])");
+        // only CA of the root counts, not the text of other properties
+        assert_eq!(
+            text(b"(;N[CA[GB2312\\]]C[caf\xe9])").unwrap(),
+            "(;N[CA[GB2312\\]]C[café])"
+        );
+    }
+
+    #[test]
+    fn test_replaces_other_file() {
+        let dir = env::temp_dir();
+        let game = dir.join(format!("sgf-navigator-{}.sgf", process::id()));
+        let game = game.to_string_lossy().into_owned();
+        let member = dir.join(format!("games.zip/2001/sgf-navigator-{}.sgf", process::id()));
+        let member = member.to_string_lossy().into_owned();
+        assert_eq!(save_name(&member), game);
+        assert!(!replaces_other_file(&member));
+        fs::write(&game, "(;)").unwrap();
+        // the file read is replaced, other ones are not, like a.sgf for a.gib
+        let gib = game.replace(".sgf", ".gib");
+        let replaces = (
+            replaces_other_file(&member),
+            replaces_other_file(&gib),
+            replaces_other_file(&game),
+        );
+        let _ = fs::remove_file(&game);
+        assert_eq!(replaces, (true, true, false));
+        assert_eq!(save_name("-"), "stdin.sgf");
+        assert_eq!(save_name("-/2001/game.sgf"), "game.sgf");
+    }
+
+    #[test]
+    fn test_save_latin1() {
+        let name = |n: &str| {
+            let file = format!("sgf-navigator-{}-{}.sgf", n, process::id());
+            env::temp_dir().join(file).to_string_lossy().into_owned()
+        };
+        let (latin1, saved) = (name("latin1"), name("saved"));
+        fs::write(&latin1, b"(;GM[1]CA[ISO-8859-1]C[caf\xe9])").unwrap();
+        let mut collection = load_collection(&latin1).unwrap();
+        let mut game = get_board(collection.games[0].clone()).unwrap();
+        save(&mut game, &mut collection, 0, &saved).unwrap();
+        let bytes = fs::read(&saved).unwrap();
+        let _ = fs::remove_file(&latin1);
+        let _ = fs::remove_file(&saved);
+        assert_eq!(String::from_utf8(bytes).unwrap(), "(;GM[1]CA[UTF-8]C[café])");
+    }
 }
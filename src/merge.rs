@@ -0,0 +1,314 @@
+// Merging several copies of a game, e.g. the reviews of a game sent back by
+// different people, into one game tree. Nodes are matched by their moves
+// from the root, everything else is added as new variations.
+
+use std::io::prelude::*;
+use std::io;
+
+use game_tree::GameNode;
+use {
+    board_size, load_collection, point_list, position_to_str, GAME_INFO_PROPERTIES, MARKS,
+    MOVE_ANNOTATIONS, POSITION_ANNOTATIONS, ROOT_PROPERTIES, SETUP_PROPERTIES,
+};
+
+// merges the first game of the files and writes the result to stdout,
+// conflicts are reported on stderr
+pub fn merge_files(file_names: &[String]) -> Result<(), String> {
+    if file_names.len() < 2 {
+        return Err("Usage: sgf-navigator merge file1 file2 ...".to_string());
+    }
+    let mut collection = load_collection(&file_names[0])?;
+    if collection.games.is_empty() {
+        return Err(format!("{} contains no game", file_names[0]));
+    }
+    let who = contributor(&collection.games[0], &file_names[0]);
+    tag_comments(&mut collection.games[0], &who);
+
+    for file_name in &file_names[1..] {
+        let mut other = load_collection(file_name)?;
+        if other.games.is_empty() {
+            eprintln!("{}: contains no game", file_name);
+            continue;
+        }
+        let other = other.games.remove(0);
+        let who = contributor(&other, file_name);
+        for conflict in merge_game(&mut collection.games[0], other, &who) {
+            eprintln!("{}: {}", file_name, conflict);
+        }
+    }
+    io::stdout()
+        .write_all(collection.to_string().as_bytes())
+        .map_err(|why| format!("couldn't write the merged game: {}", why))
+}
+
+// the annotator or user of the game, or else the name of the file
+fn contributor(root: &GameNode, file_name: &str) -> String {
+    root.get_simple_text("AN")
+        .or_else(|_| root.get_simple_text("US"))
+        .unwrap_or_else(|_| {
+            let stem = ::std::path::Path::new(file_name).file_stem();
+            stem.map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| file_name.to_string())
+        })
+}
+
+// puts the name of the contributor in front of all comments
+fn tag_comments(node: &mut GameNode, who: &str) {
+    let mut nodes = vec![node];
+    while let Some(node) = nodes.pop() {
+        if let Ok(comment) = node.get_text("C") {
+            node.set_text("C", &format!("[{}] {}", who, comment));
+        }
+        nodes.extend(node.children.iter_mut());
+    }
+}
+
+// merges the other game into the base game and returns the conflicts
+fn merge_game(base: &mut GameNode, other: GameNode, who: &str) -> Vec<String> {
+    let mut conflicts = vec![];
+    if board_size(base) != board_size(&other) {
+        conflicts.push("the board size differs, not merged".to_string());
+    } else if setup(base) != setup(&other) {
+        conflicts.push("the setup of the game differs, not merged".to_string());
+    } else {
+        merge_node(base, other, who, 0, &mut conflicts);
+    }
+    conflicts
+}
+
+// the setup stones and player to move of a node
+fn setup(node: &GameNode) -> (Vec<Vec<(usize, usize)>>, Option<char>) {
+    let stones = SETUP_PROPERTIES[..3]
+        .iter()
+        .map(|id| {
+            let mut points = point_list(node, id);
+            points.sort();
+            points
+        })
+        .collect();
+    (stones, node.get_color("PL").ok().map(|c| c.to_ascii_uppercase()))
+}
+
+fn get_move(node: &GameNode) -> Option<(&'static str, String)> {
+    ["B", "W"]
+        .iter()
+        .filter_map(|&id| node.get_point(id).ok().map(|p| (id, p)))
+        .next()
+}
+
+// merges two nodes at the same place in the game, moves is the number of
+// moves before them
+fn merge_node(
+    base: &mut GameNode,
+    other: GameNode,
+    who: &str,
+    moves: usize,
+    conflicts: &mut Vec<String>,
+) {
+    let moves = moves + get_move(&other).map_or(0, |_| 1);
+    // comments that are there already, like those of the original game, are
+    // not repeated
+    if let Ok(comment) = other.get_text("C") {
+        match base.get_text("C") {
+            Ok(ref existing) if has_comment(existing, &comment) => {}
+            Ok(existing) => {
+                base.set_text("C", &format!("{}\n\n[{}] {}", existing, who, comment));
+            }
+            Err(_) => {
+                base.set_text("C", &format!("[{}] {}", who, comment));
+            }
+        }
+    }
+    merge_markup(base, &other, moves, conflicts);
+    // annotations and anything else the base doesn't have, values that differ
+    // are conflicts and the base keeps its own, like for the game info
+    for id in other.property_ids() {
+        let skipped = ["C", "AN", "US", "AR", "LN", "DD", "SL"];
+        if skipped.contains(&id) || MARKS.contains(&id) || ROOT_PROPERTIES.contains(&id) {
+            continue;
+        }
+        let values = other.get_points(id).unwrap();
+        match base.get_points(id) {
+            Ok(ref existing) if *existing == values => {}
+            Ok(_) => conflicts.push(format!(
+                "conflicting {} after move {}, kept the first",
+                id, moves
+            )),
+            // the game info is only taken from the base
+            Err(_) if GAME_INFO_PROPERTIES.contains(&id) => {}
+            Err(_) => {
+                // only one annotation of a group is allowed in a node
+                let exclusive = [&MOVE_ANNOTATIONS, &POSITION_ANNOTATIONS]
+                    .iter()
+                    .find(|group| group.contains(&id))
+                    .and_then(|group| group.iter().find(|a| base.get_points(a).is_ok()));
+                match exclusive {
+                    Some(kept) => conflicts.push(format!(
+                        "{} conflicts with {} after move {}, kept {}",
+                        id, kept, moves, kept
+                    )),
+                    None => {
+                        base.set_property(id, values);
+                    }
+                }
+            }
+        }
+    }
+
+    for mut child in other.children {
+        let found = base.children.iter().position(|c| {
+            get_move(c) == get_move(&child) && (get_move(c).is_some() || setup(c) == setup(&child))
+        });
+        match found {
+            Some(i) => {
+                if setup(&base.children[i]) != setup(&child) {
+                    conflicts.push(format!("conflicting setup after move {}, kept the first", moves));
+                }
+                merge_node(&mut base.children[i], child, who, moves, conflicts);
+            }
+            None => {
+                if get_move(&child).is_none() && base.children.iter().any(|c| get_move(c).is_none()) {
+                    conflicts.push(format!(
+                        "conflicting setup after move {}, added as a variation",
+                        moves
+                    ));
+                }
+                tag_comments(&mut child, who);
+                base.children.push(child);
+            }
+        }
+    }
+}
+
+// whether all paragraphs of the comment are in the existing comment, the
+// paragraphs there may be tagged with a contributor
+fn has_comment(existing: &str, comment: &str) -> bool {
+    comment.split("\n\n").all(|p| {
+        existing
+            .split("\n\n")
+            .any(|e| e == p || untagged(e) == p)
+    })
+}
+
+// a paragraph without the "[name] " in front
+fn untagged(paragraph: &str) -> &str {
+    match paragraph.find("] ") {
+        Some(i) if paragraph.starts_with('[') => &paragraph[i + 2..],
+        _ => paragraph,
+    }
+}
+
+// the marks and labels of the node by their point
+fn markup(node: &GameNode) -> Vec<(String, String)> {
+    let mut markup = vec![];
+    for id in &MARKS {
+        if *id == "LB" {
+            for value in node.get_points(id).unwrap_or_default() {
+                if let Some(i) = value.find(':') {
+                    markup.push((value[..i].to_string(), value.clone()));
+                }
+            }
+        } else {
+            for (x, y) in point_list(node, id) {
+                markup.push((position_to_str(x, y), id.to_string()));
+            }
+        }
+    }
+    markup
+}
+
+// adds the marks and labels of the other node on points without markup in
+// the base, a different mark or label on a point is a conflict, arrows,
+// lines and dimmed or selected points are combined
+fn merge_markup(base: &mut GameNode, other: &GameNode, moves: usize, conflicts: &mut Vec<String>) {
+    let existing = markup(base);
+    for (point, mark) in markup(other) {
+        match existing.iter().find(|m| m.0 == point) {
+            Some(m) if m.1 == mark => {}
+            Some(_) => conflicts.push(format!(
+                "conflicting markup at {} after move {}, kept the first",
+                point, moves
+            )),
+            None => {
+                let (id, value) = if mark.contains(':') {
+                    ("LB", mark)
+                } else {
+                    (mark.as_str(), point)
+                };
+                let id = id.to_string();
+                let mut values = base.get_points(&id).unwrap_or_default();
+                values.push(value);
+                base.set_property(&id, values);
+            }
+        }
+    }
+    for id in &["AR", "LN", "DD", "SL"] {
+        let mut values = base.get_points(id).unwrap_or_default();
+        let count = values.len();
+        for value in other.get_points(id).unwrap_or_default() {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        if values.len() > count {
+            base.set_property(id, values);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_tree::Collection;
+
+    #[test]
+    fn test_merge() {
+        let mut base = Collection::from_sgf(
+            "(;GM[1]SZ[9]C[orig];B[ee]C[center];W[cc](;B[gg]C[good])(;B[gc]))",
+        ).unwrap();
+        let mut other = Collection::from_sgf(
+            "(;GM[1]SZ[9]US[Bob]C[orig];B[ee]C[fine];W[cc]TE[1](;B[gg];W[gc])(;B[cg]C[x]))",
+        ).unwrap();
+        assert!(merge_game(&mut base.games[0], other.games.remove(0), "bob").is_empty());
+        assert_eq!(
+            base.to_string(),
+            "(;GM[1]SZ[9]C[orig];B[ee]C[center\n\n[bob\\] fine];W[cc]TE[1]\
+             (;B[gg]C[good];W[gc])(;B[gc])(;B[cg]C[[bob\\] x]))"
+        );
+
+        // the markup of both, annotations that exclude each other and a
+        // comment that is part of another one
+        let mut other = Collection::from_sgf(
+            "(;GM[1]SZ[9]C[orig];B[ee]C[ent]LB[ee:a][ff:b]CR[dd]BM[1]DD[aa];W[cc]TE[2])",
+        )
+        .unwrap();
+        let mut base = Collection::from_sgf(
+            "(;GM[1]SZ[9]C[[alice\\] orig];B[ee]C[center]TR[dd]LB[ee:a]TE[1];W[cc]TE[1])",
+        )
+        .unwrap();
+        let conflicts = merge_game(&mut base.games[0], other.games.remove(0), "bob");
+        assert_eq!(
+            conflicts,
+            [
+                "conflicting markup at dd after move 1, kept the first",
+                "BM conflicts with TE after move 1, kept TE",
+                "conflicting TE after move 2, kept the first",
+            ]
+        );
+        assert_eq!(
+            base.to_string(),
+            "(;GM[1]SZ[9]C[[alice\\] orig];B[ee]C[center\n\n[bob\\] ent]TR[dd]\
+             LB[ee:a][ff:b]TE[1]DD[aa];W[cc]TE[1])"
+        );
+
+        let mut other = Collection::from_sgf("(;GM[1]SZ[9]AB[aa];B[ee])").unwrap();
+        assert_eq!(merge_game(&mut base.games[0], other.games.remove(0), "carol").len(), 1);
+
+        let mut base = Collection::from_sgf("(;GM[1]SZ[19:13];B[ee])").unwrap();
+        let mut other = Collection::from_sgf("(;GM[1]SZ[19:9];B[ee])").unwrap();
+        assert_eq!(
+            merge_game(&mut base.games[0], other.games.remove(0), "dave"),
+            ["the board size differs, not merged"]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+// Writing a canvas as a PNG file, 8 bit RGB without interlacing.
+
+use deflate::{crc32, zlib};
+use raster::Canvas;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+fn be32(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend(&be32(data.len() as u32));
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(&be32(crc));
+}
+
+// the row after the filter, 0 none, 1 the difference to the left pixel and 2
+// to the pixel above
+fn filter(kind: u8, row: &[u8], above: &[u8]) -> Vec<u8> {
+    let mut filtered = vec![kind];
+    for i in 0..row.len() {
+        let before = match kind {
+            1 if i >= 3 => row[i - 3],
+            2 => above[i],
+            _ => 0,
+        };
+        filtered.push(row[i].wrapping_sub(before));
+    }
+    filtered
+}
+
+pub fn encode(canvas: &Canvas) -> Vec<u8> {
+    let mut out = SIGNATURE.to_vec();
+    let mut header = vec![];
+    header.extend(&be32(canvas.width as u32));
+    header.extend(&be32(canvas.height as u32));
+    // bit depth 8, RGB, deflate, the standard filters, no interlacing
+    header.extend(&[8, 2, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &header);
+
+    // each row with the filter that gives the smallest values, which
+    // usually compresses best
+    let mut data = vec![];
+    let mut above = vec![0; canvas.width * 3];
+    for y in 0..canvas.height {
+        let row: Vec<u8> = canvas.pixels[y * canvas.width..(y + 1) * canvas.width]
+            .iter()
+            .flat_map(|pixel| pixel.iter().cloned())
+            .collect();
+        let best = (0..3)
+            .map(|kind| filter(kind, &row, &above))
+            .min_by_key(|filtered| {
+                filtered[1..]
+                    .iter()
+                    .map(|&b| (b as i8).unsigned_abs() as u32)
+                    .sum::<u32>()
+            })
+            .unwrap();
+        data.extend(best);
+        above = row;
+    }
+    chunk(&mut out, b"IDAT", &zlib(&data));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let canvas = Canvas {
+            width: 2,
+            height: 1,
+            pixels: vec![[255, 0, 0], [0, 0, 255]],
+        };
+        let png = encode(&canvas);
+        assert_eq!(&png[..8], &SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        // the CRC of an empty IEND chunk
+        assert_eq!(
+            &png[png.len() - 8..],
+            &[b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+    }
+}
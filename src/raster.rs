@@ -0,0 +1,503 @@
+// Drawing a diagram into a pixel canvas, with antialiased stones and lines
+// and a small bitmap font for the coordinates, numbers and labels.
+
+use diagram::{Diagram, Mark};
+use svg::wrap;
+
+pub type Color = [u8; 3];
+
+pub struct Theme {
+    board: Color,
+    // stripes of a darker color on the board
+    grain: bool,
+    line: Color,
+    black: Color,
+    white: Color,
+    white_edge: Color,
+    // a highlight on the stones
+    shaded: bool,
+    arrow: Color,
+    selected: Color,
+}
+
+pub const THEMES: [&str; 3] = ["wood", "flat", "print"];
+
+pub fn theme(name: &str) -> Option<Theme> {
+    match name {
+        "wood" => Some(Theme {
+            board: [220, 179, 92],
+            grain: true,
+            line: [40, 30, 20],
+            black: [20, 20, 20],
+            white: [245, 245, 240],
+            white_edge: [90, 90, 90],
+            shaded: true,
+            arrow: [200, 0, 0],
+            selected: [50, 100, 200],
+        }),
+        "flat" => Some(Theme {
+            board: [232, 196, 120],
+            grain: false,
+            line: [0, 0, 0],
+            black: [30, 30, 30],
+            white: [250, 250, 250],
+            white_edge: [120, 120, 120],
+            shaded: false,
+            arrow: [200, 0, 0],
+            selected: [50, 100, 200],
+        }),
+        "print" => Some(Theme {
+            board: [255, 255, 255],
+            grain: false,
+            line: [0, 0, 0],
+            black: [0, 0, 0],
+            white: [255, 255, 255],
+            white_edge: [0, 0, 0],
+            shaded: false,
+            arrow: [0, 0, 0],
+            selected: [128, 128, 128],
+        }),
+        _ => None,
+    }
+}
+
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize, color: Color) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![color; width * height],
+        }
+    }
+
+    // mixes the color into a pixel, alpha from 0 to 1
+    fn blend(&mut self, x: isize, y: isize, color: Color, alpha: f32) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height || alpha <= 0.0 {
+            return;
+        }
+        let alpha = alpha.min(1.0);
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        for i in 0..3 {
+            pixel[i] = (pixel[i] as f32 * (1.0 - alpha) + color[i] as f32 * alpha).round() as u8;
+        }
+    }
+
+    fn fill_rect(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color, alpha: f32) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.blend(x, y, color, alpha);
+            }
+        }
+    }
+
+    // draws the pixels by their distance to a shape, inside is negative,
+    // the edges are antialiased
+    fn shape<F, C>(&mut self, bounds: (f32, f32, f32, f32), distance: F, color: C, alpha: f32)
+    where
+        F: Fn(f32, f32) -> f32,
+        C: Fn(f32, f32) -> Color,
+    {
+        let (x0, y0, x1, y1) = bounds;
+        for y in (y0.floor() as isize - 1)..(y1.ceil() as isize + 1) {
+            for x in (x0.floor() as isize - 1)..(x1.ceil() as isize + 1) {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let coverage = (0.5 - distance(px, py)).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    self.blend(x, y, color(px, py), coverage * alpha);
+                }
+            }
+        }
+    }
+
+    fn disc<C>(&mut self, cx: f32, cy: f32, r: f32, color: C, alpha: f32)
+    where
+        C: Fn(f32, f32) -> Color,
+    {
+        let distance = |x: f32, y: f32| ((x - cx).powi(2) + (y - cy).powi(2)).sqrt() - r;
+        self.shape((cx - r, cy - r, cx + r, cy + r), distance, color, alpha);
+    }
+
+    fn ring(&mut self, cx: f32, cy: f32, r: f32, width: f32, color: Color) {
+        let distance =
+            |x: f32, y: f32| (((x - cx).powi(2) + (y - cy).powi(2)).sqrt() - r).abs() - width / 2.0;
+        let e = r + width;
+        self.shape(
+            (cx - e, cy - e, cx + e, cy + e),
+            distance,
+            |_, _| color,
+            1.0,
+        );
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length2 = (dx * dx + dy * dy).max(1e-6);
+        let distance = |x: f32, y: f32| {
+            let t = (((x - from.0) * dx + (y - from.1) * dy) / length2).clamp(0.0, 1.0);
+            let (nx, ny) = (from.0 + t * dx, from.1 + t * dy);
+            ((x - nx).powi(2) + (y - ny).powi(2)).sqrt() - width / 2.0
+        };
+        let bounds = (
+            from.0.min(to.0) - width,
+            from.1.min(to.1) - width,
+            from.0.max(to.0) + width,
+            from.1.max(to.1) + width,
+        );
+        self.shape(bounds, distance, |_, _| color, 1.0);
+    }
+
+    // text centered at the point
+    fn text(&mut self, cx: f32, cy: f32, s: &str, scale: usize, color: Color) {
+        let chars: Vec<char> = s.chars().collect();
+        let width = (chars.len() * 6).saturating_sub(1) * scale;
+        let x0 = (cx - width as f32 / 2.0).round() as isize;
+        let y0 = (cy - 7.0 * scale as f32 / 2.0).round() as isize;
+        let scale = scale as isize;
+        for (i, c) in chars.iter().enumerate() {
+            let rows = glyph(*c);
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0x10 >> column) != 0 {
+                        let x = x0 + (i as isize * 6 + column) * scale;
+                        let y = y0 + row as isize * scale;
+                        self.fill_rect(x, y, x + scale, y + scale, color, 1.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// draws the diagram, the image is about size pixels wide
+pub fn render(d: &Diagram, size: usize, theme: &Theme) -> Canvas {
+    // one cell around the board for the coordinates
+    let cell = (size / (d.columns() + 2)).max(8);
+    let c = cell as f32;
+    let mut canvas = Canvas::new(cell * (d.columns() + 2), cell * (d.rows() + 2), theme.board);
+    if theme.grain {
+        for y in 0..canvas.height {
+            let shade = ((y as f32 * 0.35).sin() + (y as f32 * 0.09).sin()) * 0.5;
+            let dark = [
+                theme.board[0] - 40,
+                theme.board[1] - 40,
+                theme.board[2] - 30,
+            ];
+            for x in 0..canvas.width {
+                canvas.blend(x as isize, y as isize, dark, 0.08 + 0.06 * shade);
+            }
+        }
+    }
+
+    let width = (cell / 24).max(1) as isize;
+    // the centers of the points
+    let x_of = |x: usize| (cell * (1 + x - d.left) + cell / 2) as isize;
+    let y_of = |y: usize| (cell * (1 + y - d.top) + cell / 2) as isize;
+    let center = |x: usize, y: usize| (x_of(x) as f32 + 0.5, y_of(y) as f32 + 0.5);
+
+    // the lines go on at the sides that are cut off
+    let half = cell as isize / 2;
+    let x0 = x_of(d.left) - if d.left > 0 { half } else { 0 };
+    let x1 = x_of(d.right) + if d.right + 1 < d.width { half } else { width };
+    let y0 = y_of(d.top) - if d.top > 0 { half } else { 0 };
+    let y1 = y_of(d.bottom) + if d.bottom + 1 < d.height { half } else { width };
+    for y in d.top..d.bottom + 1 {
+        let y = y_of(y);
+        canvas.fill_rect(x0, y, x1, y + width, theme.line, 1.0);
+    }
+    for x in d.left..d.right + 1 {
+        let x = x_of(x);
+        canvas.fill_rect(x, y0, x + width, y1, theme.line, 1.0);
+    }
+    for (x, y) in d.star_points() {
+        if shown(d, x, y) {
+            let (cx, cy) = center(x, y);
+            canvas.disc(cx, cy, c * 0.1, |_, _| theme.line, 1.0);
+        }
+    }
+
+    // the font is scaled by whole pixels, smaller for longer text
+    let scale = (cell / 14).max(1);
+    let fit = |s: &str| {
+        let width = (s.chars().count() * 6).saturating_sub(1).max(1);
+        (cell * 3 / 4 / width).min(scale).max(1)
+    };
+    let columns = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+    for x in d.left..d.right + 1 {
+        let letter = columns.chars().nth(x).unwrap_or('?').to_string();
+        canvas.text(center(x, 0).0, c / 2.0, &letter, scale, theme.line);
+    }
+    for y in d.top..d.bottom + 1 {
+        let row = (d.height - y).to_string();
+        canvas.text(c / 2.0, center(0, y).1, &row, scale, theme.line);
+    }
+
+    for y in d.top..d.bottom + 1 {
+        for x in d.left..d.right + 1 {
+            let pos = y * d.width + x;
+            let (cx, cy) = center(x, y);
+            let r = c * 0.48;
+            let alpha = if d.dimmed[pos] { 0.4 } else { 1.0 };
+            // a lighter spot up left on the stones
+            let shade = |base: Color, light: Color| {
+                move |px: f32, py: f32| {
+                    if !theme.shaded {
+                        return base;
+                    }
+                    let d = ((px - cx + r * 0.35).powi(2) + (py - cy + r * 0.35).powi(2)).sqrt();
+                    let t = (1.0 - d / (r * 1.3)).max(0.0).powi(2);
+                    mix(base, light, t)
+                }
+            };
+            let ink = match d.board[pos] {
+                1 => {
+                    canvas.disc(cx, cy, r, |_, _| theme.white_edge, alpha);
+                    let light = [255, 255, 255];
+                    // shaded stones are darker at the edge
+                    let dark = if theme.shaded {
+                        mix(theme.white, theme.white_edge, 0.25)
+                    } else {
+                        theme.white
+                    };
+                    canvas.disc(cx, cy, r - (c / 20.0).max(1.0), shade(dark, light), alpha);
+                    theme.black
+                }
+                2 => {
+                    canvas.disc(cx, cy, r, shade(theme.black, [110, 110, 110]), alpha);
+                    theme.white
+                }
+                _ => theme.line,
+            };
+
+            let m = c * 0.28;
+            let stroke = (c / 14.0).max(1.0);
+            match (&d.marks[pos], d.numbers[pos]) {
+                (&Some(Mark::Label(ref label)), _) => {
+                    if d.board[pos] == 0 {
+                        // hide the lines behind the label
+                        let (lx, ly) = (cx as isize, cy as isize);
+                        let h = (c * 0.35) as isize;
+                        canvas.fill_rect(lx - h, ly - h, lx + h, ly + h, theme.board, 1.0);
+                    }
+                    canvas.text(cx, cy, label, fit(label), ink);
+                }
+                (&Some(Mark::Circle), _) => canvas.ring(cx, cy, m * 0.8, stroke, ink),
+                (&Some(Mark::Cross), _) => {
+                    let s = m * 0.7;
+                    canvas.line((cx - s, cy - s), (cx + s, cy + s), stroke, ink);
+                    canvas.line((cx - s, cy + s), (cx + s, cy - s), stroke, ink);
+                }
+                (&Some(Mark::Square), _) => {
+                    let s = m * 0.7;
+                    let corners = [
+                        (cx - s, cy - s),
+                        (cx + s, cy - s),
+                        (cx + s, cy + s),
+                        (cx - s, cy + s),
+                    ];
+                    for i in 0..4 {
+                        canvas.line(corners[i], corners[(i + 1) % 4], stroke, ink);
+                    }
+                }
+                (&Some(Mark::Triangle), _) => {
+                    let corners = [
+                        (cx, cy - m),
+                        (cx - m * 0.87, cy + m * 0.5),
+                        (cx + m * 0.87, cy + m * 0.5),
+                    ];
+                    for i in 0..3 {
+                        canvas.line(corners[i], corners[(i + 1) % 3], stroke, ink);
+                    }
+                }
+                (&None, Some(n)) => {
+                    let number = n.to_string();
+                    canvas.text(cx, cy, &number, fit(&number), ink);
+                }
+                (&None, None) => {}
+            }
+            if d.selected[pos] {
+                let (sx, sy) = (cx as isize - half, cy as isize - half);
+                let s = cell as isize;
+                canvas.fill_rect(sx, sy, sx + s, sy + s, theme.selected, 0.35);
+            }
+        }
+    }
+
+    for &((fx, fy), (tx, ty), arrow) in &d.lines {
+        if !shown(d, fx, fy) || !shown(d, tx, ty) {
+            continue;
+        }
+        let (from, to) = (center(fx, fy), center(tx, ty));
+        let stroke = (c / 12.0).max(1.5);
+        canvas.line(from, to, stroke, theme.arrow);
+        if arrow {
+            // two strokes for the head
+            let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+            let length = (dx * dx + dy * dy).sqrt();
+            let (ux, uy) = (dx / length, dy / length);
+            let head = c * 0.35;
+            for &side in &[-1.0, 1.0] {
+                let end = (
+                    to.0 - head * (ux + side * uy * 0.6),
+                    to.1 - head * (uy - side * ux * 0.6),
+                );
+                canvas.line(to, end, stroke, theme.arrow);
+            }
+        }
+    }
+    canvas
+}
+
+// the scale of the caption font and the height of a line
+fn caption_font(width: usize) -> (usize, usize) {
+    let scale = (width / 250).max(1);
+    (scale, 10 * scale)
+}
+
+// the node name and comment broken into lines that fit the width in pixels
+pub fn caption(d: &Diagram, width: usize) -> Vec<String> {
+    let (scale, _) = caption_font(width);
+    let chars = (width.saturating_sub(8 * scale) / (6 * scale)).max(1);
+    let mut lines = vec![];
+    if let Some(ref name) = d.name {
+        lines.extend(wrap(name, chars));
+    }
+    if let Some(ref comment) = d.comment {
+        for line in comment.lines() {
+            lines.extend(wrap(line, chars));
+        }
+    }
+    lines
+}
+
+// a copy of the canvas with room for rows lines of text below it
+pub fn add_caption(canvas: &Canvas, lines: &[String], rows: usize, theme: &Theme) -> Canvas {
+    let (scale, line_height) = caption_font(canvas.width);
+    let height = canvas.height + rows * line_height;
+    let mut captioned = Canvas::new(canvas.width, height, theme.board);
+    captioned.pixels[..canvas.pixels.len()].copy_from_slice(&canvas.pixels);
+    for (i, line) in lines.iter().take(rows).enumerate() {
+        let width = (line.chars().count() * 6).saturating_sub(1) * scale;
+        let cx = (4 * scale) as f32 + width as f32 / 2.0;
+        let cy = (canvas.height + i * line_height + line_height / 2) as f32;
+        captioned.text(cx, cy, line, scale, theme.line);
+    }
+    captioned
+}
+
+fn shown(d: &Diagram, x: usize, y: usize) -> bool {
+    x >= d.left && x <= d.right && y >= d.top && y <= d.bottom
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    let mut color = a;
+    for i in 0..3 {
+        color[i] = (a[i] as f32 * (1.0 - t) + b[i] as f32 * t).round() as u8;
+    }
+    color
+}
+
+// 5x7 pixel glyphs, one byte per row, lowercase letters are shown as
+// uppercase ones
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '+' => [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '/' => [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10],
+        ' ' => [0x00; 7],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model;
+
+    #[test]
+    fn test_canvas() {
+        let mut canvas = Canvas::new(20, 10, [255, 255, 255]);
+        canvas.disc(5.5, 5.5, 2.8, |_, _| [0, 0, 0], 1.0);
+        assert_eq!(canvas.pixels[5 * 20 + 5], [0, 0, 0]);
+        assert_eq!(canvas.pixels[0], [255, 255, 255]);
+        // the edge is mixed
+        let edge = canvas.pixels[5 * 20 + 8][0];
+        assert!(edge > 0 && edge < 255);
+
+        // the top row of a "1" is its middle pixel
+        canvas.text(15.0, 5.0, "1", 1, [0, 0, 0]);
+        let top: Vec<u8> = (13..18).map(|x| canvas.pixels[2 * 20 + x][0]).collect();
+        assert_eq!(top, vec![255, 255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_render() {
+        let game = model("(;SZ[9]AB[cc]AW[gg]MA[gc]CR[cc])");
+        let diagram = Diagram::new(&game).unwrap();
+        let canvas = render(&diagram, 220, &theme("print").unwrap());
+        let pixel = |x: usize, y: usize| canvas.pixels[y * canvas.width + x];
+        let (black, white) = ([0, 0, 0], [255, 255, 255]);
+        assert_eq!((canvas.width, canvas.height), (220, 220));
+        // the points are 20 pixels apart, C7 is at 70, 70
+        assert_eq!(pixel(70, 70), black);
+        // the circle on it
+        assert_eq!(pixel(66, 68), white);
+        // the lines are hidden by the white stone on G3
+        assert_eq!(pixel(150, 150), white);
+        // tengen, but not the point next to it, is a star point
+        assert_eq!(pixel(109, 109), black);
+        assert_eq!(pixel(89, 109), white);
+        // the cross on G7 beside the lines
+        assert_eq!(pixel(147, 67), black);
+        assert_eq!(pixel(147, 63), white);
+    }
+}
@@ -0,0 +1,305 @@
+// Writing a diagram as SVG, for web pages and print.
+
+use diagram::{Diagram, Mark};
+
+const CELL: f32 = 30.0;
+const MARGIN: f32 = 30.0;
+const LINE_HEIGHT: f32 = 18.0;
+const BOARD_COLOR: &str = "#dcb35c";
+
+pub fn to_svg(d: &Diagram) -> String {
+    let board_width = d.columns() as f32 * CELL;
+    let board_height = d.rows() as f32 * CELL;
+    let width = board_width + 2.0 * MARGIN;
+    // the caption, wrapped to the width of the image
+    let chars = (width / 7.5) as usize;
+    let mut caption: Vec<(bool, String)> = vec![];
+    if let Some(ref name) = d.name {
+        caption.push((true, name.clone()));
+    }
+    if let Some(ref comment) = d.comment {
+        for line in comment.lines() {
+            caption.extend(wrap(line, chars).into_iter().map(|l| (false, l)));
+        }
+    }
+    if !d.footnotes.is_empty() {
+        caption.extend(
+            wrap(&d.footnotes.join(", "), chars)
+                .into_iter()
+                .map(|l| (false, l)),
+        );
+    }
+    let height = board_height + 2.0 * MARGIN + caption.len() as f32 * LINE_HEIGHT;
+
+    let x_of = |x: usize| MARGIN + (x - d.left) as f32 * CELL + CELL / 2.0;
+    let y_of = |y: usize| MARGIN + (y - d.top) as f32 * CELL + CELL / 2.0;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\">\n",
+        w = width,
+        h = height
+    );
+    svg.push_str(
+        "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"9\" refY=\"5\" \
+         markerWidth=\"6\" markerHeight=\"6\" orient=\"auto\">\
+         <path d=\"M0,0 L10,5 L0,10 z\" fill=\"#c00\"/></marker></defs>\n",
+    );
+    svg.push_str(&format!(
+        "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"white\"/>\n",
+        width, height
+    ));
+    svg.push_str(&format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+        MARGIN, MARGIN, board_width, board_height, BOARD_COLOR
+    ));
+
+    // the lines go on at the sides that are cut off
+    let half = CELL / 2.0;
+    let (x0, x1) = (
+        x_of(d.left) - if d.left > 0 { half } else { 0.0 },
+        x_of(d.right) + if d.right + 1 < d.width { half } else { 0.0 },
+    );
+    let (y0, y1) = (
+        y_of(d.top) - if d.top > 0 { half } else { 0.0 },
+        y_of(d.bottom) + if d.bottom + 1 < d.height { half } else { 0.0 },
+    );
+    for y in d.top..d.bottom + 1 {
+        svg.push_str(&line(x0, y_of(y), x1, y_of(y), "black", 1.0));
+    }
+    for x in d.left..d.right + 1 {
+        svg.push_str(&line(x_of(x), y0, x_of(x), y1, "black", 1.0));
+    }
+    for (x, y) in d.star_points() {
+        if shown(d, x, y) {
+            svg.push_str(&format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"black\"/>\n",
+                x_of(x),
+                y_of(y)
+            ));
+        }
+    }
+
+    // coordinates
+    let columns = "ABCDEFGHJKLMNOPQRSTUVWXYZabcdefghjklmnopqrstuvwxyz";
+    for x in d.left..d.right + 1 {
+        let letter = columns.chars().nth(x).unwrap_or('?');
+        svg.push_str(&text(
+            x_of(x),
+            MARGIN / 2.0,
+            &letter.to_string(),
+            "black",
+            12.0,
+        ));
+    }
+    for y in d.top..d.bottom + 1 {
+        let row = (d.height - y).to_string();
+        svg.push_str(&text(MARGIN / 2.0, y_of(y), &row, "black", 12.0));
+    }
+
+    for y in d.top..d.bottom + 1 {
+        for x in d.left..d.right + 1 {
+            let pos = y * d.width + x;
+            let (cx, cy) = (x_of(x), y_of(y));
+            let opacity = if d.dimmed[pos] { 0.4 } else { 1.0 };
+            let ink = match d.board[pos] {
+                1 => {
+                    svg.push_str(&format!(
+                        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"white\" stroke=\"black\" \
+                         opacity=\"{}\"/>\n",
+                        cx,
+                        cy,
+                        CELL * 0.47,
+                        opacity
+                    ));
+                    "black"
+                }
+                2 => {
+                    svg.push_str(&format!(
+                        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"black\" opacity=\"{}\"/>\n",
+                        cx,
+                        cy,
+                        CELL * 0.48,
+                        opacity
+                    ));
+                    "white"
+                }
+                _ => "black",
+            };
+            let r = CELL * 0.3;
+            match (&d.marks[pos], d.numbers[pos]) {
+                (&Some(Mark::Label(ref label)), _) => {
+                    if d.board[pos] == 0 {
+                        // hide the lines behind the label
+                        svg.push_str(&format!(
+                            "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"{}\"/>\n",
+                            cx - r,
+                            cy - r,
+                            BOARD_COLOR,
+                            s = 2.0 * r
+                        ));
+                    }
+                    svg.push_str(&text(cx, cy, label, ink, 14.0));
+                }
+                (&Some(Mark::Circle), _) => svg.push_str(&format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"{}\" \
+                     stroke-width=\"2\"/>\n",
+                    cx,
+                    cy,
+                    r * 0.8,
+                    ink
+                )),
+                (&Some(Mark::Cross), _) => {
+                    let s = r * 0.6;
+                    svg.push_str(&line(cx - s, cy - s, cx + s, cy + s, ink, 2.0));
+                    svg.push_str(&line(cx - s, cy + s, cx + s, cy - s, ink, 2.0));
+                }
+                (&Some(Mark::Square), _) => svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"none\" \
+                     stroke=\"{}\" stroke-width=\"2\"/>\n",
+                    cx - r * 0.7,
+                    cy - r * 0.7,
+                    ink,
+                    s = r * 1.4
+                )),
+                (&Some(Mark::Triangle), _) => svg.push_str(&format!(
+                    "<polygon points=\"{},{} {},{} {},{}\" fill=\"none\" stroke=\"{}\" \
+                     stroke-width=\"2\"/>\n",
+                    cx,
+                    cy - r,
+                    cx - r * 0.87,
+                    cy + r * 0.5,
+                    cx + r * 0.87,
+                    cy + r * 0.5,
+                    ink
+                )),
+                (&None, Some(n)) => {
+                    let size = if n >= 100 { 11.0 } else { 13.0 };
+                    svg.push_str(&text(cx, cy, &n.to_string(), ink, size));
+                }
+                (&None, None) => {}
+            }
+            if d.selected[pos] {
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"#36c\" \
+                     opacity=\"0.35\"/>\n",
+                    cx - half,
+                    cy - half,
+                    s = CELL
+                ));
+            }
+        }
+    }
+
+    for &((fx, fy), (tx, ty), arrow) in &d.lines {
+        if !shown(d, fx, fy) || !shown(d, tx, ty) {
+            continue;
+        }
+        svg.push_str(&format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#c00\" stroke-width=\"2\"{}/>\n",
+            x_of(fx),
+            y_of(fy),
+            x_of(tx),
+            y_of(ty),
+            if arrow { " marker-end=\"url(#arrow)\"" } else { "" }
+        ));
+    }
+
+    let mut y = board_height + MARGIN * 1.5 + LINE_HEIGHT / 2.0;
+    for (bold, line) in caption {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"13\"{}>{}</text>\n",
+            MARGIN,
+            y,
+            if bold { " font-weight=\"bold\"" } else { "" },
+            escape(&line)
+        ));
+        y += LINE_HEIGHT;
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn shown(d: &Diagram, x: usize, y: usize) -> bool {
+    x >= d.left && x <= d.right && y >= d.top && y <= d.bottom
+}
+
+fn line(x1: f32, y1: f32, x2: f32, y2: f32, color: &str, width: f32) -> String {
+    format!(
+        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+        x1, y1, x2, y2, color, width
+    )
+}
+
+// centered text
+fn text(x: f32, y: f32, s: &str, color: &str, size: f32) -> String {
+    format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\" text-anchor=\"middle\" \
+         dominant-baseline=\"central\">{}</text>\n",
+        x,
+        y,
+        size,
+        color,
+        escape(s)
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// breaks a line of text into lines of at most width characters
+pub fn wrap(s: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in s.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + word.chars().count() + 1 > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("a bb ccc dddd", 6), vec!["a bb", "ccc", "dddd"]);
+        assert_eq!(escape("<b> & \"c\""), "&lt;b&gt; &amp; &quot;c&quot;");
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let mut game = model("(;SZ[13]AW[gg];B[dd]N[Joseki]LB[jd:a][zz:b]AR[dd:jd][aa])");
+        game.path = vec![0];
+        let diagram = Diagram::new(&game).unwrap();
+        assert_eq!(
+            diagram.star_points(),
+            [(3, 3), (9, 3), (3, 9), (9, 9), (6, 6)]
+        );
+        let svg = to_svg(&diagram);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"450\""));
+        assert_eq!(svg.matches("r=\"3\" fill=\"black\"").count(), 5);
+        assert!(svg.contains("<circle cx=\"135\" cy=\"135\" r=\"14.4\" fill=\"black\""));
+        assert!(svg.contains("<circle cx=\"225\" cy=\"225\" r=\"14.1\" fill=\"white\""));
+        assert!(svg.contains("dominant-baseline=\"central\">a</text>"));
+        assert!(svg.contains("x1=\"135\" y1=\"135\" x2=\"315\" y2=\"135\" stroke=\"#c00\""));
+        assert!(svg.ends_with("font-weight=\"bold\">Joseki</text>\n</svg>\n"));
+        game.path = vec![1];
+        assert!(Diagram::new(&game).is_err());
+    }
+}
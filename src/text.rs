@@ -0,0 +1,243 @@
+// Plain text diagrams for forums and chat: the wiki format of Sensei's
+// Library and board drawn with Unicode box characters.
+
+use diagram::{Diagram, Mark};
+
+// the color of the stone with the lowest number, moves alternate from it
+fn first_player(d: &Diagram) -> Option<(i32, u8)> {
+    (0..d.board.len())
+        .filter_map(|pos| d.numbers[pos].map(|n| (n, d.board[pos])))
+        .min()
+}
+
+// the diagram in the format of Sensei's Library, moves are numbered from 1
+// to 10 as in the wiki, the numbers after that are shown as plain stones
+pub fn to_sensei(d: &Diagram) -> String {
+    let first = first_player(d);
+    let mut header = String::from("$$");
+    header.push(match first {
+        Some((_, 1)) => 'W',
+        _ => 'B',
+    });
+    let full = d.columns() == d.width && d.rows() == d.height;
+    if full {
+        header.push('c');
+    }
+    if d.width != 19 || d.height != 19 {
+        header.push_str(&d.width.max(d.height).to_string());
+    }
+    if let Some((n, _)) = first {
+        if n > 1 {
+            header.push_str(&format!("m{}", n - 1));
+        }
+    }
+    if let Some(ref name) = d.name {
+        header.push(' ');
+        header.push_str(name.lines().next().unwrap_or(""));
+    }
+
+    let star_points = d.star_points();
+    let mut rows = vec![];
+    for y in d.top..d.bottom + 1 {
+        let mut points = vec![];
+        if d.left == 0 {
+            points.push("|".to_string());
+        }
+        for x in d.left..d.right + 1 {
+            let pos = y * d.width + x;
+            let stone = d.board[pos];
+            let c = match (&d.marks[pos], d.numbers[pos]) {
+                (&Some(Mark::Circle), _) => ['C', 'W', 'B'][stone as usize],
+                (&Some(Mark::Square), _) => ['S', '@', '#'][stone as usize],
+                (&Some(Mark::Triangle), _) => ['T', 'Q', 'Y'][stone as usize],
+                (&Some(Mark::Cross), _) => ['M', 'P', 'Z'][stone as usize],
+                (&Some(Mark::Label(ref label)), _)
+                    if stone == 0
+                        && label.len() == 1
+                        && label.chars().all(|c| c.is_ascii_lowercase()) =>
+                {
+                    label.chars().next().unwrap()
+                }
+                (_, Some(n)) if first.is_some_and(|(f, _)| n - f < 10) => {
+                    let n = n - first.unwrap().0 + 1;
+                    if n == 10 {
+                        '0'
+                    } else {
+                        (b'0' + n as u8) as char
+                    }
+                }
+                _ if stone == 1 => 'O',
+                _ if stone == 2 => 'X',
+                _ if star_points.contains(&(x, y)) => ',',
+                _ => '.',
+            };
+            points.push(c.to_string());
+        }
+        if d.right + 1 == d.width {
+            points.push("|".to_string());
+        }
+        rows.push(format!("$$ {}", points.join(" ")));
+    }
+    // the edges of the board at the top and the bottom
+    let border = {
+        let prefix = if d.left == 0 { "$$  " } else { "$$ " };
+        let right = if d.right + 1 == d.width { 1 } else { 0 };
+        let length = rows[0].chars().count() - prefix.len() - right;
+        format!("{}{}", prefix, "-".repeat(length))
+    };
+
+    let mut lines = vec![header];
+    if d.top == 0 {
+        lines.push(border.clone());
+    }
+    lines.extend(rows);
+    if d.bottom + 1 == d.height {
+        lines.push(border);
+    }
+    lines.join("\n") + "\n"
+}
+
+// white stones with the numbers 1 to 50, black ones with 1 to 20
+fn numbered_stone(stone: u8, n: i32) -> Option<char> {
+    let code = match (stone, n) {
+        (1, 1..=20) => 0x2460 + n - 1,
+        (1, 21..=35) => 0x3251 + n - 21,
+        (1, 36..=50) => 0x32b1 + n - 36,
+        (2, 1..=10) => 0x2776 + n - 1,
+        (2, 11..=20) => 0x24eb + n - 11,
+        _ => return None,
+    };
+    ::std::char::from_u32(code as u32)
+}
+
+// the diagram drawn with box characters, stones and marks, without any
+// terminal colors
+pub fn to_unicode(d: &Diagram) -> String {
+    let columns = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+    let star_points = d.star_points();
+    let mut lines = vec![];
+    let letters: Vec<String> = (d.left..d.right + 1)
+        .map(|x| columns.chars().nth(x).unwrap_or('?').to_string())
+        .collect();
+    lines.push(format!("   {}", letters.join(" ")));
+    for y in d.top..d.bottom + 1 {
+        let mut line = format!("{:>2} ", d.height - y);
+        for x in d.left..d.right + 1 {
+            let pos = y * d.width + x;
+            let stone = d.board[pos];
+            let c = match (&d.marks[pos], d.numbers[pos]) {
+                (&Some(Mark::Label(ref label)), _) if stone == 0 && label.chars().count() == 1 => {
+                    label.chars().next().unwrap()
+                }
+                // on white stones the marks have a dot or a circle, on black
+                // stones they are filled
+                (&Some(Mark::Circle), _) => ['◯', '◎', '◉'][stone as usize],
+                (&Some(Mark::Square), _) => ['□', '⊡', '▣'][stone as usize],
+                (&Some(Mark::Triangle), _) => ['△', '◬', '▲'][stone as usize],
+                (&Some(Mark::Cross), _) => ['×', '⊗', '✖'][stone as usize],
+                (_, Some(n)) if numbered_stone(stone, n).is_some() => {
+                    numbered_stone(stone, n).unwrap()
+                }
+                _ if stone == 1 => '○',
+                _ if stone == 2 => '●',
+                _ if star_points.contains(&(x, y)) => '╋',
+                _ => {
+                    let top = y == 0;
+                    let bottom = y + 1 == d.height;
+                    let left = x == 0;
+                    let right = x + 1 == d.width;
+                    match (top, bottom, left, right) {
+                        (true, _, true, _) => '┌',
+                        (true, _, _, true) => '┐',
+                        (_, true, true, _) => '└',
+                        (_, true, _, true) => '┘',
+                        (true, _, _, _) => '┬',
+                        (_, true, _, _) => '┴',
+                        (_, _, true, _) => '├',
+                        (_, _, _, true) => '┤',
+                        _ => '┼',
+                    }
+                }
+            };
+            line.push(c);
+            if x < d.right {
+                line.push('─');
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagram(size: usize) -> Diagram {
+        let n = size * size;
+        Diagram {
+            width: size,
+            height: size,
+            left: 0,
+            top: 0,
+            right: size - 1,
+            bottom: size - 1,
+            board: vec![0; n],
+            numbers: vec![None; n],
+            marks: (0..n).map(|_| None).collect(),
+            selected: vec![false; n],
+            dimmed: vec![false; n],
+            lines: vec![],
+            name: None,
+            comment: None,
+            footnotes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        let mut d = diagram(5);
+        d.board[6] = 2;
+        d.numbers[6] = Some(21);
+        d.board[7] = 1;
+        d.numbers[7] = Some(22);
+        d.board[12] = 1;
+        d.marks[12] = Some(Mark::Triangle);
+        d.marks[18] = Some(Mark::Label("a".to_string()));
+        d.name = Some("Test".to_string());
+        assert_eq!(
+            to_sensei(&d),
+            "$$Bc5m20 Test\n\
+             $$  -----------\n\
+             $$ | . . . . . |\n\
+             $$ | . 1 2 . . |\n\
+             $$ | . . Q . . |\n\
+             $$ | . . . a . |\n\
+             $$ | . . . . . |\n\
+             $$  -----------\n"
+        );
+        d.crop((1, 1), (4, 2));
+        assert_eq!(to_sensei(&d), "$$B5m20 Test\n$$ 1 2 . . |\n$$ . Q . . |\n");
+
+        d.crop((0, 0), (4, 4));
+        d.numbers[6] = Some(1);
+        d.numbers[7] = None;
+        for &(pos, stone, ref mark) in &[
+            (8, 2, Mark::Cross),
+            (11, 1, Mark::Cross),
+            (13, 1, Mark::Square),
+        ] {
+            d.board[pos] = stone;
+            d.marks[pos] = Some(mark.clone());
+        }
+        assert_eq!(
+            to_unicode(&d),
+            "   A B C D E\n \
+             5 ┌─┬─┬─┬─┐\n \
+             4 ├─❶─○─✖─┤\n \
+             3 ├─⊗─◬─⊡─┤\n \
+             2 ├─┼─┼─a─┤\n \
+             1 └─┴─┴─┴─┘\n"
+        );
+    }
+}